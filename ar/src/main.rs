@@ -0,0 +1,59 @@
+use std::fs;
+use std::path::Path;
+use q16::archive::Archive;
+use q16::util::{ArgParser, err_msg};
+
+fn main() {
+  let mut args = ArgParser::from_env();
+  if let Some(path) = args.take_flag("--list") {
+    return list(&path);
+  }
+  let out_path = match args.take_flag("-o") {
+    Some(p) => p,
+    None => return print_help(),
+  };
+  let paths = args.remaining();
+  if paths.is_empty() {
+    return print_help();
+  }
+
+  let mut archive = Archive::new();
+  for path in paths {
+    let data = match fs::read(&path) {
+      Ok(d) => d,
+      Err(e) => err_msg(&format!("couldn't open '{}': {}", path, e), None),
+    };
+    // members are named by file name, so the archive doesnt depend on where it was built
+    let name = Path::new(&path)
+      .file_name()
+      .map(|n| n.to_string_lossy().to_string())
+      .unwrap_or(path);
+    if let Err(e) = archive.add(name, data) {
+      err_msg(&format!("couldn't add to archive: {}", e), None);
+    }
+  }
+  if fs::write(&out_path, archive.out()).is_err() {
+    err_msg(&format!("could not write to {:?}", out_path), None);
+  }
+}
+
+fn list(path: &str) {
+  let archive = match fs::read(path).map_err(|e| e.to_string()).and_then(|b| Archive::load(&b)) {
+    Ok(a) => a,
+    Err(e) => err_msg(&format!("couldn't open '{}': {}", path, e), None),
+  };
+  let symbols = archive.symbols();
+  for (i, m) in archive.members.iter().enumerate() {
+    println!("{} ({} bytes)", m.name, m.data.len());
+    for (label, _) in symbols.iter().filter(|(_, member)| *member == i) {
+      println!("  {}", label);
+    }
+  }
+}
+
+fn print_help() {
+  println!("q16-ar help:");
+  println!("usage: q16-ar [input objects] -o <out archive>");
+  println!("       q16-ar --list <archive>");
+  println!("archives can be passed to q16-ld, which links only the members defining labels that are otherwise undefined");
+}
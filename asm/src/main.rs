@@ -1,6 +1,6 @@
 use std::fs;
 use q16::asm::Assembler;
-use q16::util::{ArgParser, err_msg};
+use q16::util::{ArgParser, Severity, err_msg};
 
 fn main() {
   let mut args = ArgParser::from_env();
@@ -8,6 +8,11 @@ fn main() {
     Some(p) => p,
     None => return print_help(),
   };
+  let listing_path = args.take_flag("--listing");
+  let mut defines = vec![];
+  while let Some(d) = args.take_flag("-D") {
+    defines.push(d);
+  }
   let paths = args.remaining();
   if paths.len() != 1 {
     return print_help();
@@ -20,10 +25,28 @@ fn main() {
   };
 
   let mut assembler = Assembler::new();
-  let obj = match assembler.assemble(&src) {
-    Ok(_) => assembler.obj,
-    Err((n, e)) => err_msg(&e, Some((&format!("{}:{}", src_path, n + 1), src.lines().nth(n).unwrap().trim()))),
-  };
+  assembler.set_file(src_path);
+  for d in defines {
+    let (name, value) = d.split_once('=').unwrap_or((&d, "1"));
+    if let Err(e) = assembler.define(name, value) {
+      err_msg(&format!("invalid definition '-D {}': {}", d, e), None);
+    }
+  }
+  let result = assembler.assemble(&src);
+  let (Ok(diagnostics) | Err(diagnostics)) = &result;
+  for d in diagnostics {
+    d.print(src_path, &src);
+  }
+  if result.is_err() {
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    err_msg(&format!("couldn't assemble {:?} due to {} error(s)", src_path, errors), None);
+  }
+  if let Some(path) = listing_path {
+    if fs::write(&path, assembler.listing(&src)).is_err() {
+      err_msg(&format!("could not write to {:?}", path), None);
+    }
+  }
+  let obj = assembler.obj;
 
   if fs::write(&out_path, obj.out_obj()).is_err() {
     err_msg(&format!("could not write to {:?}", out_path), None);
@@ -32,5 +55,5 @@ fn main() {
 
 fn print_help() {
   println!("q16-asm help:");
-  println!("usage: q16-asm <input file> -o <out object>");
+  println!("usage: q16-asm <input file> -o <out object> [--listing <out listing>] [-D <name>[=value]]...");
 }
@@ -1,6 +1,9 @@
+.extern start
+
 mov %sp, stack
 
 jmp start
 
+.bss
 .skip 1024
 stack: ; allocate stack
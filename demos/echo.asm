@@ -1,3 +1,4 @@
+.global start
 start:
   lw %r1, 0xf000
   cmp %r1, 0
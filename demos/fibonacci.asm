@@ -1,10 +1,11 @@
+.global start
 start:
   mov %r6, 44 ; ','
   mov %r2, 0
   mov %r3, 1
   mov %r4, 0
   
-  loop:
+  .loop:
     mov %r1, %r2
     add %ra, %pc, 4
     jmp print_int
@@ -17,7 +18,7 @@ start:
 
     inc %r4
     cmp %r4, 24 ; only print first 24 terms, limited by 16bit int limit
-    jgt loop
+    jgt .loop
 
   hlt
   jmp start
@@ -26,7 +27,7 @@ start:
 print_int:
   mov %r8, %sp
   ; push ascii chars to the stack
-  conv_loop:
+  .conv_loop:
     rem %r7, %r1, 10
     add %r7, %r7, 48 ; '0'
     div %r1, %r1, 10
@@ -35,15 +36,15 @@ print_int:
     sb %r7, %sp
 
     cmp %r1, 0
-    jne conv_loop
+    jne .conv_loop
 
   ; pop and print each char
-  output_loop:
+  .output_loop:
     lb %r7, %sp
     sb %r7, 0xf002
     add %sp, %sp, 1
   
     cmp %r8, %sp
-    jne output_loop
+    jne .output_loop
 
   jmp %ra
@@ -1,3 +1,4 @@
+.global start
 start:
   mov %r8, 0xff
   sb %r8, 0xd840 ; (64, 48)
@@ -9,7 +10,7 @@ start:
 
 cycle:
   mov %r1, 0
-  game_loop:
+  .game_loop:
     add %r4, %r1, 0xbf7f
     add %ra, %pc, 4
     jmp wrap_r4
@@ -60,39 +61,39 @@ cycle:
 
     lbu %r3, %r1, 0xc000
     cmp %r2, 0x1fe ; 2 * 0xff
-    jeq continue
+    jeq .continue
     cmp %r2, 0x2fd ; 3 * 0xff
-    jne die
+    jne .die
     mov %r3, 0xff
-    jmp continue
-    die:
+    jmp .continue
+    .die:
     mov %r3, 0
-    continue:
+    .continue:
     sb %r3, %r1, 0x2000
 
     inc %r1
     cmp %r1, 0x3000
-    jne game_loop
+    jne .game_loop
 
   ; the game array is first written to 0x2000 before being copied to vram
   mov %r1, 0
-  blit_loop:
+  .blit_loop:
     lw %r2, %r1, 0x2000
     sw %r2, %r1, 0xc000
 
     add %r1, %r1, 2
     cmp %r1, 0x3000
-    jne blit_loop
+    jne .blit_loop
 
   jmp cycle
 
 wrap_r4:
     cmp %r4, 0xc000
-    jge out1
+    jge .out1
     add %r4, %r4, 0x3000
-  out1:
+  .out1:
     cmp %r4, 0xefff
-    jle out2
+    jle .out2
     sub %r4, %r4, 0x3000
-  out2:
+  .out2:
     jmp %ra
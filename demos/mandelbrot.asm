@@ -1,7 +1,8 @@
 ; draw the mandelbrot set centered at (-0.75, 0)
+.global start
 start:
   mov %r1, 0
-  mandelbrot_display_loop:
+  .display_loop:
     ; cx=r2, cy=r3, x=r4, y=r5, iter=r6
     rem %r2, %r1, 128
     mul %r2, %r2, 2
@@ -12,12 +13,12 @@ start:
     mov %r4, 0
     mov %r5, 0
     mov %r6, 0
-    mandelbrot_iter_loop:
+    .iter_loop:
       mul %r7, %r4, %r4 ; r7 = x^2
       mul %r8, %r5, %r5 ; r8 = y^2
       add %r8, %r7, %r8 ; r8 = x^2+y^2
       cmp %r8, 32400 ; break if x^2+y^2 > 4 * 90^2
-      jlt mandelbrot_iter_continue
+      jlt .iter_continue
       mul %r8, %r5, %r5 ; r8 = y^2
       sub %r8, %r7, %r8 ; r8 = x^2-y^2
       div %r7, %r8, 90 ; r7 = (x^2-y^2)/90
@@ -28,18 +29,18 @@ start:
 
       inc %r6
       cmp %r6, 50 ; 50 iterations
-      jgt mandelbrot_iter_loop
-    jmp mandelbrot_draw_skip
+      jgt .iter_loop
+    jmp .draw_skip
 
-    mandelbrot_iter_continue:
+    .iter_continue:
     div %r6, %r6, 10
     add %r6, %r6, 0b11000011
     sb %r6, %r1, 0xc000 ; draw pixel
-    mandelbrot_draw_skip:
+    .draw_skip:
     
     inc %r1
     cmp %r1, 0x3000 ; 128*96
-    jne mandelbrot_display_loop
+    jne .display_loop
 
     hlt
     jmp start
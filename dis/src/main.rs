@@ -0,0 +1,73 @@
+use std::fs;
+use q16::obj::Obj;
+use q16::disasm::Disassembler;
+use q16::util::{ArgParser, err_msg};
+
+fn main() {
+  let mut args = ArgParser::from_env();
+  let base = match args.take_flag("--base").map(|b| parse_addr(&b)) {
+    Some(Some(b)) => b,
+    Some(None) => return print_help(),
+    None => 0,
+  };
+  let paths = args.remaining();
+  if paths.len() != 1 {
+    return print_help();
+  }
+  let path = &paths[0];
+
+  let data = match fs::read(path) {
+    Ok(d) => d,
+    Err(_) => err_msg(&format!("couldn't open {:?}", path), None),
+  };
+  // objects carry a symbol table, anything else is treated as a raw binary
+  match Obj::load(&data) {
+    Ok(obj) => {
+      for (i, section) in obj.sections.iter().enumerate() {
+        if section.data.is_empty() && !obj.labels().any(|(_, loc)| loc.section == i) {
+          continue;
+        }
+        // the first section is always .text, which is where assembly starts
+        if i > 0 {
+          match section.name.as_str() {
+            ".text" | ".data" | ".bss" => println!("{}", section.name),
+            name => println!(".section {}", name),
+          }
+        }
+        print_lines(&Disassembler::from_section(&obj, i));
+      }
+    }
+    Err(_) => print_lines(&Disassembler::new(&data, base)),
+  }
+}
+
+fn print_lines(dis: &Disassembler) {
+  for line in dis.lines() {
+    for label in line.labels {
+      println!("{}:", label);
+    }
+    if line.text.is_empty() {
+      continue;
+    }
+    let bytes = line
+      .bytes
+      .iter()
+      .take(4)
+      .map(|b| format!("{:02x}", b))
+      .collect::<Vec<_>>()
+      .join(" ");
+    println!("  {:<32} ; {:04x}: {}", line.text, line.addr, bytes);
+  }
+}
+
+fn parse_addr(s: &str) -> Option<u16> {
+  match s.strip_prefix("0x") {
+    Some(hex) => u16::from_str_radix(hex, 16).ok(),
+    None => s.parse().ok(),
+  }
+}
+
+fn print_help() {
+  println!("q16-dis help:");
+  println!("usage: q16-dis <input object/binary> [--base <load address>]");
+}
@@ -3,12 +3,14 @@ mod ui;
 use std::{fs, thread};
 use std::sync::{Arc, Mutex};
 use std::time::{Instant, Duration};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::path::{Path, PathBuf};
 use eframe::egui;
 use time::OffsetDateTime;
 use q16::{Instruction, addr};
-use q16::emu::{Emulator, MEM_LEN};
+use q16::debug::DebugInfo;
+use q16::emu::Emulator;
+use q16::image;
 use q16::util::{CircularBuffer, ArgParser};
 use crate::ui::{Window, CpuStateWindow, MemoryWindow, DisplayWindow, SerialWindow, LogWindow};
 
@@ -126,6 +128,10 @@ struct EmuState {
   msg_log: Vec<(OffsetDateTime, String)>,
   serial_in_queue: VecDeque<u8>,
   serial_out: Vec<u8>,
+  /// from the `.dbg` file written by the linker next to the binary
+  debug: Option<DebugInfo>,
+  /// the lines of each source file named by the debug info which could be read
+  sources: HashMap<String, Vec<String>>,
 }
 
 impl EmuState {
@@ -138,23 +144,70 @@ impl EmuState {
       msg_log: vec![],
       serial_in_queue: VecDeque::new(),
       serial_out: vec![],
+      debug: None,
+      sources: HashMap::new(),
     }
   }
 
   pub fn load_binary<P: AsRef<Path>>(&mut self, path: P) {
-    match fs::read(&path) {
-      Ok(bin) if bin.len() <= MEM_LEN => {
+    // raw binaries are loaded at 0, intel hex and s-records wherever their records say
+    match fs::read(&path).map_err(|e| e.to_string()).and_then(|data| image::decode(&data)) {
+      Ok(segments) => {
         self.emu.reset();
-        self.emu.memory.splice(..bin.len(), bin);
+        for (addr, data) in segments {
+          let addr = addr as usize;
+          self.emu.memory.splice(addr..addr + data.len(), data);
+        }
         self.last_instr = None;
         self.log(format!("Loaded binary from '{}'.", path.as_ref().display()));
+        self.load_debug(path.as_ref().with_extension("dbg"));
       }
-      _ => self.log(format!("Couldn't load '{}'.", path.as_ref().display())),
+      Err(e) => self.log(format!("Couldn't load '{}': {}", path.as_ref().display(), e)),
+    };
+  }
+
+  fn load_debug(&mut self, path: PathBuf) {
+    self.debug = None;
+    self.sources.clear();
+    let Ok(data) = fs::read(&path) else {
+      return;
     };
+    match DebugInfo::load(&data) {
+      Ok(debug) => {
+        for file in debug.files() {
+          if let Ok(src) = fs::read_to_string(file) {
+            self
+              .sources
+              .insert(file.clone(), src.lines().map(|l| l.trim().to_string()).collect());
+          }
+        }
+        self.debug = Some(debug);
+        self.log(format!("Loaded debug info from '{}'.", path.display()));
+      }
+      Err(e) => self.log(format!("Couldn't load debug info from '{}': {}", path.display(), e)),
+    }
+  }
+
+  /// the source location and text of the instruction at an address, if there is debug info
+  pub fn source_at(&self, addr: u16) -> Option<String> {
+    let (file, line) = self.debug.as_ref()?.line_at(addr)?;
+    Some(match self.sources.get(file).and_then(|s| s.get(line as usize)) {
+      Some(text) => format!("{}:{}  {}", file, line + 1, text),
+      None => format!("{}:{}", file, line + 1),
+    })
+  }
+
+  /// the nearest label at or before an address, if there is debug info
+  pub fn label_at(&self, addr: u16) -> Option<String> {
+    let (label, offset) = self.debug.as_ref()?.symbol_at(addr)?;
+    Some(match offset {
+      0 => label.to_string(),
+      _ => format!("{}+0x{:x}", label, offset),
+    })
   }
 
   pub fn load_state<P: AsRef<Path>>(&mut self, path: P) {
-    match fs::read(&path).ok().and_then(|b| Emulator::from_state(b)) {
+    match fs::read(&path).ok().and_then(Emulator::from_state) {
       Some(emu) => {
         self.emu = emu;
         self.last_instr = None;
@@ -41,7 +41,7 @@ impl Window for CpuStateWindow {
       {
         state.time_history.clear();
       }
-      if state.time_history.len() > 0 {
+      if !state.time_history.is_empty() {
         let measured_speed =
           ONE_SEC_NANOS / (state.time_history.items().iter().sum::<Duration>().as_nanos() as u64 / state.time_history.len() as u64);
         ui.label(format!("(actual: {}Hz)", measured_speed));
@@ -54,6 +54,17 @@ impl Window for CpuStateWindow {
       ui.label("Last instruction:");
       ui.monospace(state.last_instr.map(|i| i.to_string()).unwrap_or("---".to_string()));
     });
+    if state.debug.is_some() {
+      let pc = state.emu.registers.pc;
+      ui.horizontal(|ui| {
+        ui.label("Label:");
+        ui.monospace(state.label_at(pc).unwrap_or("---".to_string()));
+      });
+      ui.horizontal(|ui| {
+        ui.label("Source:");
+        ui.monospace(state.source_at(pc).unwrap_or("---".to_string()));
+      });
+    }
 
     ui.separator();
     ui.heading("Registers:");
@@ -0,0 +1,153 @@
+use std::{fs, process};
+use q16::asm::{Operand, Statement};
+use q16::util::{ArgParser, err_msg};
+
+const INDENT: &str = "  ";
+
+fn main() {
+  let mut args = ArgParser::from_env();
+  let check = args.take_switch("--check");
+  let paths = args.remaining();
+  if paths.is_empty() {
+    return print_help();
+  }
+
+  let mut unformatted = 0;
+  for path in paths {
+    let src = match fs::read_to_string(&path) {
+      Ok(s) => s,
+      Err(_) => err_msg(&format!("couldn't open {:?}", path), None),
+    };
+    let formatted = format(&src);
+    if formatted == src {
+      continue;
+    }
+    if check {
+      println!("{} is not formatted", path);
+      unformatted += 1;
+    } else if fs::write(&path, formatted).is_err() {
+      err_msg(&format!("could not write to {:?}", path), None);
+    }
+  }
+  if unformatted > 0 {
+    process::exit(1);
+  }
+}
+
+/// labels are unindented, with local labels and instructions indented beneath the label they follow
+/// trailing comments are aligned within each block of consecutive lines
+fn format(src: &str) -> String {
+  let stmts: Vec<_> = src.lines().map(Statement::parse).collect();
+
+  // the depth of each line of code, comment only lines take the depth of the code after them
+  let mut depths = vec![0; stmts.len()];
+  let mut depth = 0;
+  for (i, stmt) in stmts.iter().enumerate() {
+    depths[i] = match stmt.label.as_ref().map(|l| l.text) {
+      Some(l) if is_local(l) => {
+        depth = 2;
+        1
+      }
+      Some(_) => {
+        depth = 1;
+        0
+      }
+      None => depth,
+    };
+  }
+  let mut next = 0;
+  for (i, stmt) in stmts.iter().enumerate().rev() {
+    if stmt.code.text.is_empty() {
+      depths[i] = next;
+    } else {
+      next = depths[i];
+    }
+  }
+
+  let lines: Vec<_> = stmts
+    .iter()
+    .zip(depths)
+    .map(
+      |(stmt, depth)| match (stmt.code.text.is_empty(), stmt.comment.as_ref().map(|c| c.text)) {
+        (true, None) => String::new(),
+        (true, Some(c)) => format!("{};{}", INDENT.repeat(depth), c),
+        (false, _) => INDENT.repeat(depth) + &format_code(stmt),
+      },
+    )
+    .collect();
+
+  let mut out = String::new();
+  let mut block_start = 0;
+  for i in 0..=lines.len() {
+    // blocks are separated by blank lines
+    let blank = stmts.get(i).is_none_or(|s| s.code.text.is_empty() && s.comment.is_none());
+    if !blank {
+      continue;
+    }
+    let block = block_start..i;
+    let width = block
+      .clone()
+      .filter(|j| !stmts[*j].code.text.is_empty() && stmts[*j].comment.is_some())
+      .map(|j| lines[j].len())
+      .max()
+      .unwrap_or(0);
+    for j in block {
+      let line = match stmts[j].comment.as_ref().map(|c| c.text) {
+        Some(c) if !stmts[j].code.text.is_empty() => format!("{:<width$} ;{}", lines[j], c, width = width),
+        _ => lines[j].clone(),
+      };
+      out.push_str(line.trim_end());
+      out.push('\n');
+    }
+    // collapse runs of blank lines, and drop leading and trailing ones
+    if i < lines.len() && !out.is_empty() && !out.ends_with("\n\n") {
+      out.push('\n');
+    }
+    block_start = i + 1;
+  }
+  while out.ends_with("\n\n") {
+    out.pop();
+  }
+  out
+}
+
+fn format_code(stmt: &Statement) -> String {
+  if let Some(label) = &stmt.label {
+    return format!("{}:", label.text);
+  }
+  let mnemonic = stmt.mnemonic.as_ref().map(|m| m.text.to_lowercase()).unwrap_or_default();
+  let operands: Vec<_> = stmt
+    .operands
+    .iter()
+    .map(|op| match op.value {
+      Ok(Operand::Register(r)) => format!("%{}", r),
+      _ => op.text.to_string(),
+    })
+    .collect();
+  match operands.is_empty() {
+    true => mnemonic,
+    false => format!("{} {}", mnemonic, operands.join(", ")),
+  }
+}
+
+fn is_local(label: &str) -> bool {
+  label.starts_with('.') || label.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn print_help() {
+  println!("q16-fmt help:");
+  println!("usage: q16-fmt [--check] <input files>");
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_format() {
+    let src = "\n\nstart:\nMOV %R1,0 ; a\n    .loop:\n inc   %r1;b\n\n\n; c\n  jne .loop\nend:\n    hlt ;assert r1=1\n\n";
+    let expected = "start:\n  mov %r1, 0 ; a\n  .loop:\n    inc %r1  ;b\n\n    ; c\n    jne .loop\nend:\n  hlt ;assert r1=1\n";
+    assert_eq!(format(src), expected);
+    assert_eq!(format(expected), expected);
+  }
+}
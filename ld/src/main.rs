@@ -1,5 +1,10 @@
 use std::fs;
+use std::path::Path;
 use q16::obj::Obj;
+use q16::archive::{self, Archive};
+use q16::debug::DebugInfo;
+use q16::image::{self, Format};
+use q16::link::{self, LinkConfig, parse_addr};
 use q16::util::{ArgParser, err_msg};
 
 fn main() {
@@ -8,32 +13,132 @@ fn main() {
     Some(p) => p,
     None => return print_help(),
   };
+  let config = match link_config(&mut args) {
+    Ok(c) => c,
+    Err(e) => err_msg(&e, None),
+  };
+  let format = match args.take_flag("--format").map(|f| f.parse::<Format>()) {
+    Some(Ok(f)) => f,
+    Some(Err(_)) => err_msg("unknown --format, expected bin, ihex or srec", None),
+    None => Format::Bin,
+  };
+  let map_path = args.take_flag("--map");
+  let entry = args.take_flag("--entry").unwrap_or("start".to_string());
+  let gc_entry = args.take_switch("--gc-sections").then_some(entry);
   let paths = args.remaining();
   if paths.is_empty() {
     return print_help();
   }
 
-  let mut out = Obj::new();
+  let mut names = vec![];
+  let mut objs = vec![];
+  let mut archives = vec![];
   for path in paths {
-    let obj = match fs::read(&path).map_err(|e| e.to_string()).and_then(|b| Obj::load(&b)) {
-      Ok(r) => r,
+    let data = match fs::read(&path) {
+      Ok(d) => d,
       Err(e) => err_msg(&format!("couldn't open '{}': {}", path, e), None),
     };
-    if let Err(e) = out.extend(obj) {
-      err_msg(&format!("couldn't link '{}': {}", path, e), None);
+    // objects are always linked, archives only for the labels they resolve once every object is in
+    let loaded = match Archive::is_archive(&data) {
+      true => Archive::load(&data).map(|a| archives.push((path.clone(), a))),
+      false => Obj::load(&data).map(|o| {
+        objs.push(o);
+        names.push(path.clone());
+      }),
+    };
+    if let Err(e) = loaded {
+      err_msg(&format!("couldn't open '{}': {}", path, e), None);
+    }
+  }
+  let (archive_names, archives): (Vec<_>, Vec<_>) = archives.into_iter().unzip();
+  match archive::resolve(&mut objs, &archives) {
+    Ok(added) => names.extend(
+      added
+        .into_iter()
+        .map(|(a, m)| format!("{}({})", archive_names[a], archives[a].members[m].name)),
+    ),
+    Err(e) => err_msg(&format!("couldn't link '{}': {}", archive_names.join("', '"), e), None),
+  }
+
+  if let Some(entry) = gc_entry {
+    match link::gc(&mut objs, &entry, &config) {
+      Ok((data, zero)) => println!(
+        "--gc-sections removed {} unreferenced bytes of data and {} of zero sections",
+        data, zero
+      ),
+      Err(e) => err_msg(&e, None),
+    }
+  }
+
+  let mut out = Obj::new();
+  let mut inputs = vec![];
+  for (name, obj) in names.into_iter().zip(objs) {
+    match out.extend(obj) {
+      Ok(placed) => inputs.push((name, placed)),
+      Err(e) => err_msg(&format!("couldn't link '{}': {}", name, e), None),
     }
   }
 
-  let bin = match out.out_bin() {
-    Ok(b) => b,
+  let addrs = match out.layout(&config) {
+    Ok(a) => a,
     Err(e) => err_msg(&e, None),
   };
-  if fs::write(&out_path, bin).is_err() {
+  if let Some(map_path) = map_path {
+    if fs::write(&map_path, link::map(&out, &addrs, &inputs)).is_err() {
+      err_msg(&format!("could not write to {:?}", map_path), None);
+    }
+  }
+  let debug = DebugInfo::new(&out, &addrs);
+
+  let segments = match out.out_image(&config) {
+    Ok(s) => s,
+    Err(e) => err_msg(&e, None),
+  };
+  if fs::write(&out_path, image::encode(&segments, format)).is_err() {
     err_msg(&format!("could not write to {:?}", out_path), None);
   }
+  // the emulator looks for debug info next to the binary
+  let debug_path = Path::new(&out_path).with_extension("dbg");
+  if !debug.is_empty() && fs::write(&debug_path, debug.out()).is_err() {
+    err_msg(&format!("could not write to {:?}", debug_path), None);
+  }
+}
+
+/// a script is read first, then flags override it
+fn link_config(args: &mut ArgParser) -> Result<LinkConfig, String> {
+  let mut config = match args.take_flag("--script") {
+    Some(path) => match fs::read_to_string(&path) {
+      Ok(script) => LinkConfig::parse(&script).map_err(|e| format!("{}: {}", path, e))?,
+      Err(_) => return Err(format!("couldn't open {:?}", path)),
+    },
+    None => LinkConfig::default(),
+  };
+  if let Some(base) = args.take_flag("--base") {
+    config.base = parse_addr(&base)?;
+  }
+  while let Some(section) = args.take_flag("--section") {
+    match section.split_once('=') {
+      Some((name, addr)) => {
+        config.sections.retain(|(s, _)| s != name);
+        config.place(name, parse_addr(addr)?)?;
+      }
+      None => return Err(format!("expected '--section <name>=<addr>', found '{}'", section)),
+    }
+  }
+  Ok(config)
 }
 
 fn print_help() {
   println!("q16-ld help:");
-  println!("usage: q16-ld [input objects] -o <out binary>");
+  println!("usage: q16-ld [input objects and archives] -o <out binary> [options]");
+  println!("  --script <file>        read the base address and section addresses from a linker script");
+  println!("  --base <addr>          place sections from this address, default 0");
+  println!("  --section <name>=<addr> place a section at a fixed address");
+  println!("  --format <format>      bin, ihex or srec, default bin");
+  println!("  --map <file>           write where each section, object and symbol was placed");
+  println!("  --gc-sections          drop sections which aren't reachable from the entry label, the first object's code or a --section");
+  println!("  --entry <label>        the entry label for --gc-sections, default start");
+  println!("a bin starts at address 0, zero filled up to the first section, ihex and srec records carry their own addresses");
+  println!("members of archives are only linked if they define a label which is otherwise undefined");
+  println!("source lines and labels are written to a .dbg file next to the binary, for q16-emu");
 }
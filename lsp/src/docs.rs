@@ -0,0 +1,80 @@
+use q16::Register;
+
+/// every mnemonic the assembler accepts, with its usage and a short description
+#[rustfmt::skip]
+pub const MNEMONICS: &[(&str, &str, &str)] = &[
+  ("add", "add %rd, %r1, %r2/imm", "rd <- r1 + r2/imm"),
+  ("sub", "sub %rd, %r1, %r2/imm", "rd <- r1 - r2/imm, the immediate form is assembled as `add` with the immediate negated"),
+  ("mul", "mul %rd, %r1, %r2/imm", "rd <- r1 * r2/imm"),
+  ("div", "div %rd, %r1, %r2/imm", "rd <- r1 / r2/imm, signed. division by zero gives 0xffff"),
+  ("rem", "rem %rd, %r1, %r2/imm", "rd <- r1 % r2/imm, signed. division by zero gives 0xffff"),
+  ("and", "and %rd, %r1, %r2/imm", "rd <- r1 & r2/imm"),
+  ("or", "or %rd, %r1, %r2/imm", "rd <- r1 | r2/imm"),
+  ("xor", "xor %rd, %r1, %r2/imm", "rd <- r1 ^ r2/imm"),
+  ("lb", "lb %rd, %r1, imm", "loads 8 bits from [r1+imm] into rd sign extended"),
+  ("lbu", "lbu %rd, %r1, imm", "loads 8 bits from [r1+imm] into rd zero extended"),
+  ("lw", "lw %rd, %r1, imm", "loads 16 bits from [r1+imm] into rd"),
+  ("sb", "sb %rd, %r1, imm", "writes 8 bits from rd to [r1+imm]"),
+  ("sw", "sw %rd, %r1, imm", "writes 16 bits from rd to [r1+imm]"),
+  ("jeq", "jeq %r1, imm", "jumps to r1+imm if the zero flag is set"),
+  ("jne", "jne %r1, imm", "jumps to r1+imm if the zero flag is not set"),
+  ("jgt", "jgt %r1, imm", "jumps to r1+imm if the zero flag is not set and the negative flag is set"),
+  ("jlt", "jlt %r1, imm", "jumps to r1+imm if neither the zero nor negative flag is set"),
+  ("jge", "jge %r1, imm", "jumps to r1+imm if the negative flag is not set"),
+  ("jle", "jle %r1, imm", "jumps to r1+imm if the zero or negative flag is set"),
+  ("nop", "nop", "does nothing, `add %r0, %r0, %r0`"),
+  ("hlt", "hlt", "stops the cpu by clearing the run bit, `and %sts, %sts, 0xfeff`"),
+  ("mov", "mov %rd, %r1/imm", "`add %rd, %r0, %r1/imm`"),
+  ("li", "li %rd, imm", "loads an immediate, `add %rd, %r0, imm`"),
+  ("la", "la %rd, label", "loads the address of a label, `add %rd, %r0, label`"),
+  ("neg", "neg %rd, %r1", "`sub %rd, %r0, %r1`"),
+  ("not", "not %rd, %r1", "`xor %rd, %r1, -1`"),
+  ("cmp", "cmp %r1, %r2/imm", "sets the flags from r1 - r2/imm, `sub %r0, %r1, %r2` or `add %r0, %r1, -imm`"),
+  ("inc", "inc %rd", "`add %rd, %rd, 1`"),
+  ("jmp", "jmp %r1/imm or jmp %r1, imm", "`add %pc, %r1, imm`"),
+  ("br", "br label", "branches relative to pc, `add %pc, %pc, label`"),
+  ("beq", "beq label", "`jeq %pc, label`, relative to pc"),
+  ("bne", "bne label", "`jne %pc, label`, relative to pc"),
+  ("bgt", "bgt label", "`jgt %pc, label`, relative to pc, taken if the zero flag is not set and the negative flag is set"),
+  ("blt", "blt label", "`jlt %pc, label`, relative to pc, taken if neither the zero nor negative flag is set"),
+  ("bge", "bge label", "`jge %pc, label`, relative to pc"),
+  ("ble", "ble label", "`jle %pc, label`, relative to pc"),
+  (".db", ".db imm/label", "inserts a byte, such as `lo(label)` or `hi(label)`"),
+  (".dw", ".dw imm/label", "inserts a little endian word, labels may have an addend such as `label + 2`"),
+  (".equ", ".equ NAME, imm", "defines a constant which is substituted wherever it is used"),
+  (".skip", ".skip n", "inserts n zero bytes"),
+  (".fill", ".fill n, imm", "inserts n copies of a byte"),
+  (".align", ".align n", "pads with zeroes to a multiple of n, which must be a power of two"),
+  (".org", ".org addr", "pads with zeroes up to an address relative to the start of the section"),
+  (".text", ".text", "assembles into the code section"),
+  (".data", ".data", "assembles into the data section"),
+  (".bss", ".bss", "assembles into the zero filled section, which takes no space in the binary"),
+  (".section", ".section name", "assembles into a named section, which is zero filled if it begins with `.bss`"),
+  (".global", ".global label", "exports a label to other objects, the default for labels which aren't scoped"),
+  (".local", ".local label", "keeps a label private to this file"),
+  (".weak", ".weak label", "exports a label which a global label of the same name in another object replaces"),
+  (".extern", ".extern label", "declares a label defined in another object, checked when linking"),
+  (".if", ".if imm", "assembles the block if the value is non-zero"),
+  (".ifdef", ".ifdef NAME", "assembles the block if the constant or label is defined"),
+  (".ifndef", ".ifndef NAME", "assembles the block if the constant or label is not defined"),
+  (".else", ".else", "starts the alternative of a conditional block"),
+  (".endif", ".endif", "ends a conditional block"),
+];
+
+pub fn mnemonic(name: &str) -> Option<(&'static str, &'static str)> {
+  MNEMONICS
+    .iter()
+    .find(|(m, _, _)| m.eq_ignore_ascii_case(name))
+    .map(|(_, usage, desc)| (*usage, *desc))
+}
+
+pub fn register(r: Register) -> &'static str {
+  match r {
+    Register::R0 => "hardwired to zero, writes are a no-op",
+    Register::PC => "program counter",
+    Register::SP => "stack pointer",
+    Register::RA => "return address",
+    Register::STS => "status register, bit 0 is zero, bit 1 is negative and bit 8 is run",
+    _ => "general purpose register",
+  }
+}
@@ -0,0 +1,134 @@
+use std::ops::Range;
+use q16::{Instruction, Register};
+use q16::asm::{Assembler, Operand, Statement};
+use q16::util::Diagnostic;
+use crate::docs;
+
+/// an assembled source file, positions are a line and byte offset within it
+pub struct Document {
+  pub src: String,
+  pub assembler: Assembler,
+  pub diagnostics: Vec<Diagnostic>,
+}
+
+/// what the cursor is over in a line of source
+enum Token<'a> {
+  Mnemonic(&'a str),
+  Register(Register),
+  Label,
+}
+
+impl Document {
+  pub fn new(src: String) -> Self {
+    let mut assembler = Assembler::new();
+    let diagnostics = match assembler.assemble(&src) {
+      Ok(d) | Err(d) => d,
+    };
+    Self {
+      src,
+      assembler,
+      diagnostics,
+    }
+  }
+
+  pub fn line(&self, line: usize) -> &str {
+    self.src.lines().nth(line).unwrap_or_default()
+  }
+
+  /// the resolved name of the label defined or referenced at a position
+  pub fn label_at(&self, line: usize, col: usize) -> Option<String> {
+    let contains = |span: &Range<usize>| span.start <= col && col <= span.end;
+    match self.assembler.definitions().find(|(_, l, span)| *l == line && contains(span)) {
+      Some((label, _, _)) => Some(label.to_string()),
+      None => self
+        .assembler
+        .references()
+        .iter()
+        .find(|r| r.line == line && contains(&r.span))
+        .map(|r| r.label.clone()),
+    }
+  }
+
+  pub fn definition(&self, label: &str) -> Option<(usize, Range<usize>)> {
+    self
+      .assembler
+      .definitions()
+      .find(|(l, _, _)| *l == label)
+      .map(|(_, line, span)| (line, span))
+  }
+
+  pub fn references(&self, label: &str) -> Vec<(usize, Range<usize>)> {
+    self
+      .assembler
+      .references()
+      .iter()
+      .filter(|r| r.label == label)
+      .map(|r| (r.line, r.span.clone()))
+      .collect()
+  }
+
+  /// markdown describing whatever is at a position
+  pub fn hover(&self, line: usize, col: usize) -> Option<String> {
+    if let Some(label) = self.label_at(line, col) {
+      return Some(match self.assembler.obj.labels().find(|(l, _)| *l == label) {
+        Some((_, loc)) => format!(
+          "`{}`\n\noffset `0x{:04x}` in `{}`",
+          label, loc.offset, self.assembler.obj.sections[loc.section].name
+        ),
+        None => format!("`{}`\n\nnot defined in this file", label),
+      });
+    }
+    match token_at(self.line(line), col)? {
+      Token::Mnemonic(m) => {
+        let (usage, desc) = docs::mnemonic(m)?;
+        let mut out = format!("```\n{}\n```\n{}", usage, desc);
+        let bytes = self.assembler.line_bytes(line);
+        if !bytes.is_empty() {
+          let hex: Vec<_> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+          out += &format!("\n\nencoding: `{}`", hex.join(" "));
+          let instrs: Vec<_> = bytes
+            .chunks_exact(4)
+            .filter_map(|c| Instruction::from_u32(u32::from_le_bytes(c.try_into().unwrap())))
+            .map(|i| i.to_string())
+            .collect();
+          if bytes.len().is_multiple_of(4) && instrs.len() == bytes.len() / 4 {
+            out += &format!(" (`{}`)", instrs.join("; "));
+          }
+        }
+        Some(out)
+      }
+      Token::Register(r) => Some(format!("`%{}`\n\n{}", r, docs::register(r))),
+      Token::Label => None,
+    }
+  }
+
+  /// the global label which local labels on `line` are scoped to
+  pub fn scope_at(&self, line: usize) -> Option<&str> {
+    self
+      .assembler
+      .definitions()
+      .filter(|(l, def_line, _)| *def_line <= line && !is_local(l))
+      .max_by_key(|(_, def_line, _)| *def_line)
+      .map(|(l, _, _)| l)
+  }
+}
+
+/// local labels are stored qualified with their scope, eg `main.loop`
+pub fn is_local(label: &str) -> bool {
+  label.contains('.')
+}
+
+fn token_at(line: &str, col: usize) -> Option<Token<'_>> {
+  let stmt = Statement::parse(line);
+  let contains = |span: &Range<usize>| span.start <= col && col <= span.end;
+  if stmt.label.as_ref().is_some_and(|l| contains(&l.span)) {
+    return Some(Token::Label);
+  }
+  if let Some(m) = stmt.mnemonic.filter(|m| contains(&m.span)) {
+    return Some(Token::Mnemonic(m.text));
+  }
+  match stmt.operands.iter().find(|op| contains(&op.span))?.value {
+    Ok(Operand::Register(r)) => Some(Token::Register(r)),
+    _ => None,
+  }
+}
@@ -0,0 +1,350 @@
+mod docs;
+mod document;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::{fs, process};
+use serde_json::{json, Value};
+use q16::Register;
+use q16::util::Severity;
+use strum::IntoEnumIterator;
+use document::{Document, is_local};
+
+/// language server for q16 assembly, speaking json-rpc over stdio
+fn main() {
+  let mut server = Server {
+    open: HashMap::new(),
+    shutdown: false,
+  };
+  let mut stdin = io::stdin().lock();
+  while let Some(msg) = read_message(&mut stdin) {
+    server.handle(msg);
+  }
+}
+
+struct Server {
+  /// open documents by uri
+  open: HashMap<String, Document>,
+  shutdown: bool,
+}
+
+impl Server {
+  fn handle(&mut self, msg: Value) {
+    let method = msg["method"].as_str().unwrap_or_default();
+    let params = &msg["params"];
+    let result = match method {
+      "initialize" => {
+        json!({
+          "capabilities": {
+            "textDocumentSync": 1,
+            "definitionProvider": true,
+            "referencesProvider": true,
+            "hoverProvider": true,
+            "completionProvider": { "triggerCharacters": ["%", "."] },
+          },
+          "serverInfo": { "name": "q16-lsp" },
+        })
+      }
+      "shutdown" => {
+        self.shutdown = true;
+        Value::Null
+      }
+      "exit" => process::exit(if self.shutdown { 0 } else { 1 }),
+      "textDocument/didOpen" => {
+        let doc = &params["textDocument"];
+        self.update(doc["uri"].as_str().unwrap_or_default(), doc["text"].as_str().unwrap_or_default());
+        return;
+      }
+      "textDocument/didChange" => {
+        // only full document sync is supported, so the last change is the whole text
+        if let Some(text) = params["contentChanges"].as_array().and_then(|c| c.last()) {
+          self.update(
+            params["textDocument"]["uri"].as_str().unwrap_or_default(),
+            text["text"].as_str().unwrap_or_default(),
+          );
+        }
+        return;
+      }
+      "textDocument/didClose" => {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        self.open.remove(uri);
+        notify("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": [] }));
+        return;
+      }
+      "textDocument/definition" => self.definition(params),
+      "textDocument/references" => self.references(params),
+      "textDocument/hover" => self.hover(params),
+      "textDocument/completion" => self.completion(params),
+      _ if msg.get("id").is_none() => return,
+      _ => return respond_error(&msg["id"], -32601, &format!("unsupported method '{}'", method)),
+    };
+    respond(&msg["id"], result);
+  }
+
+  fn update(&mut self, uri: &str, text: &str) {
+    let doc = Document::new(text.to_string());
+    let diagnostics: Vec<_> = doc
+      .diagnostics
+      .iter()
+      .map(|d| {
+        let mut message = d.msg.clone();
+        for note in &d.notes {
+          message += &format!("\nnote: {}", note);
+        }
+        json!({
+          "range": range(doc.line(d.line), d.line, &d.span),
+          "severity": match d.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+          },
+          "source": "q16-asm",
+          "message": message,
+        })
+      })
+      .collect();
+    notify("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": diagnostics }));
+    self.open.insert(uri.to_string(), doc);
+  }
+
+  /// the document and position a request refers to
+  fn position<'a>(&'a self, params: &Value) -> Option<(&'a str, &'a Document, usize, usize)> {
+    let (uri, doc) = self.open.get_key_value(params["textDocument"]["uri"].as_str()?)?;
+    let line = params["position"]["line"].as_u64()? as usize;
+    let col = from_utf16(doc.line(line), params["position"]["character"].as_u64()? as usize);
+    Some((uri, doc, line, col))
+  }
+
+  fn definition(&self, params: &Value) -> Value {
+    let Some((uri, doc, line, col)) = self.position(params) else {
+      return Value::Null;
+    };
+    let Some(label) = doc.label_at(line, col) else {
+      return Value::Null;
+    };
+    if let Some((line, span)) = doc.definition(&label) {
+      return location(uri, doc, line, &span);
+    }
+    // global labels may be defined in any file that is linked with this one
+    if is_local(&label) {
+      return Value::Null;
+    }
+    let mut found = Value::Null;
+    self.for_each_document(uri, |other, doc| {
+      if let (Value::Null, Some((line, span))) = (&found, doc.definition(&label)) {
+        found = location(other, doc, line, &span);
+      }
+    });
+    found
+  }
+
+  fn references(&self, params: &Value) -> Value {
+    let Some((uri, doc, line, col)) = self.position(params) else {
+      return Value::Null;
+    };
+    let Some(label) = doc.label_at(line, col) else {
+      return Value::Null;
+    };
+    let declaration = params["context"]["includeDeclaration"].as_bool().unwrap_or(true);
+    let mut locations = vec![];
+    let mut search = |uri: &str, doc: &Document| {
+      if declaration {
+        locations.extend(doc.definition(&label).map(|(line, span)| location(uri, doc, line, &span)));
+      }
+      for (line, span) in doc.references(&label) {
+        locations.push(location(uri, doc, line, &span));
+      }
+    };
+    match is_local(&label) {
+      true => search(uri, doc),
+      false => self.for_each_document(uri, search),
+    }
+    Value::Array(locations)
+  }
+
+  fn hover(&self, params: &Value) -> Value {
+    match self.position(params).and_then(|(_, doc, line, col)| doc.hover(line, col)) {
+      Some(text) => json!({ "contents": { "kind": "markdown", "value": text } }),
+      None => Value::Null,
+    }
+  }
+
+  /// registers after a `%`, mnemonics at the start of a line and labels in operands
+  fn completion(&self, params: &Value) -> Value {
+    let Some((_, doc, line, col)) = self.position(params) else {
+      return Value::Null;
+    };
+    let text = &doc.line(line)[..col.min(doc.line(line).len())];
+    let start = text.rfind([' ', '\t', ',']).map(|i| i + 1).unwrap_or(0);
+    let prefix = &text[start..];
+    let replace = json!({
+      "start": { "line": line, "character": to_utf16(text, start) },
+      "end": { "line": line, "character": to_utf16(text, col) },
+    });
+    let item = |label: String, kind: u32, detail: &str| {
+      json!({
+        "label": label,
+        "kind": kind,
+        "detail": detail,
+        "textEdit": { "range": replace, "newText": label },
+      })
+    };
+
+    let items: Vec<_> = if prefix.starts_with('%') {
+      Register::iter().map(|r| item(format!("%{}", r), 6, docs::register(r))).collect()
+    } else if text[..start].trim().is_empty() {
+      docs::MNEMONICS.iter().map(|(m, usage, _)| item(m.to_string(), 14, usage)).collect()
+    } else {
+      // local labels are suggested unqualified if they are in scope
+      let scope = doc.scope_at(line).map(|s| format!("{}.", s));
+      let mut labels: Vec<_> = doc
+        .assembler
+        .definitions()
+        .filter_map(|(l, _, _)| match &scope {
+          _ if !is_local(l) => Some(l.to_string()),
+          Some(s) if l.starts_with(s.as_str()) && !l.contains('#') => Some(l[s.len() - 1..].to_string()),
+          _ => None,
+        })
+        .collect();
+      labels.sort();
+      labels.into_iter().map(|l| item(l, 18, "label")).collect()
+    };
+    Value::Array(items)
+  }
+
+  /// visits every `.asm` file in the same directory as `uri`, which are the files that may be linked with it
+  /// open documents are used in place of what is on disk
+  fn for_each_document(&self, uri: &str, mut f: impl FnMut(&str, &Document)) {
+    let dir = uri_to_path(uri).and_then(|p| p.parent().map(Path::to_path_buf));
+    let in_dir = |uri: &str| uri_to_path(uri).is_some_and(|p| p.parent() == dir.as_deref());
+    for (uri, doc) in self.open.iter().filter(|(uri, _)| in_dir(uri)) {
+      f(uri, doc);
+    }
+    let Some(Ok(entries)) = dir.as_ref().map(fs::read_dir) else {
+      return;
+    };
+    for path in entries.flatten().map(|e| e.path()) {
+      let uri = path_to_uri(&path);
+      if path.extension().is_none_or(|e| e != "asm") || self.open.contains_key(&uri) {
+        continue;
+      }
+      if let Ok(src) = fs::read_to_string(&path) {
+        f(&uri, &Document::new(src));
+      }
+    }
+  }
+}
+
+fn location(uri: &str, doc: &Document, line: usize, span: &Range<usize>) -> Value {
+  json!({ "uri": uri, "range": range(doc.line(line), line, span) })
+}
+
+fn range(text: &str, line: usize, span: &Range<usize>) -> Value {
+  json!({
+    "start": { "line": line, "character": to_utf16(text, span.start) },
+    "end": { "line": line, "character": to_utf16(text, span.end) },
+  })
+}
+
+/// lsp positions count utf-16 code units, rather than bytes
+fn to_utf16(line: &str, byte: usize) -> usize {
+  line[..byte.min(line.len())].encode_utf16().count()
+}
+
+fn from_utf16(line: &str, units: usize) -> usize {
+  let mut count = 0;
+  for (i, c) in line.char_indices() {
+    if count >= units {
+      return i;
+    }
+    count += c.len_utf16();
+  }
+  line.len()
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+  let path = uri.strip_prefix("file://")?;
+  let mut bytes = vec![];
+  let mut iter = path.bytes();
+  while let Some(b) = iter.next() {
+    match b {
+      b'%' => {
+        let hex = [iter.next()?, iter.next()?];
+        bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+      }
+      _ => bytes.push(b),
+    }
+  }
+  Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+fn path_to_uri(path: &Path) -> String {
+  let mut uri = "file://".to_string();
+  for b in path.to_string_lossy().bytes() {
+    match b {
+      b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(b as char),
+      _ => uri += &format!("%{:02X}", b),
+    }
+  }
+  uri
+}
+
+/// reads a message framed by a `Content-Length` header, returning `None` at the end of input
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+  let mut len = None;
+  loop {
+    let mut header = String::new();
+    if input.read_line(&mut header).ok()? == 0 {
+      return None;
+    }
+    let header = header.trim();
+    if header.is_empty() {
+      break;
+    }
+    if let Some((name, value)) = header.split_once(':') {
+      if name.eq_ignore_ascii_case("content-length") {
+        len = value.trim().parse().ok();
+      }
+    }
+  }
+  let mut body = vec![0; len?];
+  input.read_exact(&mut body).ok()?;
+  // malformed messages are skipped rather than ending the session
+  Some(serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn send(msg: Value) {
+  let body = msg.to_string();
+  let mut stdout = io::stdout().lock();
+  let _ = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+  let _ = stdout.flush();
+}
+
+fn respond(id: &Value, result: Value) {
+  send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+}
+
+fn respond_error(id: &Value, code: i32, message: &str) {
+  send(json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }));
+}
+
+fn notify(method: &str, params: Value) {
+  send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_positions() {
+    let line = "  .db 'é' ; 😀 x";
+    let x = line.find('x').unwrap();
+    assert_eq!(from_utf16(line, to_utf16(line, x)), x);
+    assert_eq!(to_utf16(line, x), 15);
+    let path = Path::new("/tmp/my demos/a.asm");
+    assert_eq!(path_to_uri(path), "file:///tmp/my%20demos/a.asm");
+    assert_eq!(uri_to_path(&path_to_uri(path)).as_deref(), Some(path));
+  }
+}
@@ -0,0 +1,213 @@
+use std::fs;
+use serde_json::{json, Value};
+use q16::obj::{Header, Obj};
+use q16::disasm::{Disassembler, Line};
+use q16::util::{ArgParser, err_msg};
+
+/// which parts of the object to show, everything if none are chosen
+struct Parts {
+  headers: bool,
+  symbols: bool,
+  relocs: bool,
+  disassembly: bool,
+}
+
+fn main() {
+  let mut args = ArgParser::from_env();
+  let json = args.take_switch("--json");
+  let mut parts = Parts {
+    headers: args.take_switch("-h"),
+    symbols: args.take_switch("-t"),
+    relocs: args.take_switch("-r"),
+    disassembly: args.take_switch("-d"),
+  };
+  if !(parts.headers || parts.symbols || parts.relocs || parts.disassembly) {
+    parts = Parts {
+      headers: true,
+      symbols: true,
+      relocs: true,
+      disassembly: true,
+    };
+  }
+  let paths = args.remaining();
+  if paths.len() != 1 {
+    return print_help();
+  }
+  let path = &paths[0];
+
+  let data = match fs::read(path) {
+    Ok(d) => d,
+    Err(e) => err_msg(&format!("couldn't open '{}': {}", path, e), None),
+  };
+  let (header, obj) = match Header::parse(&data).and_then(|h| Ok((h, Obj::load(&data)?))) {
+    Ok(h) => h,
+    Err(e) => err_msg(&format!("couldn't open '{}': {}", path, e), None),
+  };
+  match json {
+    true => println!("{:#}", to_json(header, &obj, &parts)),
+    false => print_text(header, &obj, &parts),
+  }
+}
+
+/// labels in order of where they are defined
+fn symbols(obj: &Obj) -> Vec<(&str, usize, u16)> {
+  let mut symbols: Vec<_> = obj.labels().map(|(l, loc)| (l, loc.section, loc.offset)).collect();
+  symbols.sort_by_key(|(l, section, offset)| (*section, *offset, *l));
+  symbols
+}
+
+/// sections which have neither data nor labels are left out of the disassembly
+fn is_empty(obj: &Obj, section: usize) -> bool {
+  obj.sections[section].data.is_empty() && !obj.labels().any(|(_, loc)| loc.section == section)
+}
+
+fn print_text(header: Option<Header>, obj: &Obj, parts: &Parts) {
+  if parts.headers {
+    match header {
+      Some(h) => println!(
+        "object version {}, flags 0x{:04x}, {} sections, checksum 0x{:08x}",
+        h.version, h.flags, h.sections, h.checksum
+      ),
+      None => println!("legacy object without a header"),
+    }
+    println!();
+    println!("sections:");
+    println!("  {:<4} {:<16} {:<5} {:>5} {:>6}", "idx", "name", "kind", "align", "size");
+    for (i, s) in obj.sections.iter().enumerate() {
+      println!("  {:<4} {:<16} {:<5} {:>5} 0x{:04x}", i, s.name, s.kind, s.align, s.data.len());
+    }
+    println!();
+  }
+  if parts.symbols {
+    println!("symbols:");
+    for (label, section, offset) in symbols(obj) {
+      let binding = obj.binding(label).unwrap();
+      let place = format!("{}+0x{:04x}", obj.sections[section].name, offset);
+      println!("  {:<24} {:<6} {}", place, binding, label);
+    }
+    for label in obj.externs() {
+      println!("  {:<24} {:<6} {}", "*undefined*", "extern", label);
+    }
+    println!();
+  }
+  if parts.relocs {
+    println!("relocations:");
+    for (label, r) in obj.label_uses() {
+      let place = format!("{}+0x{:04x}", obj.sections[r.loc.section].name, r.loc.offset);
+      println!("  {:<24} {:<7} {}", place, r.kind, r.expr(label));
+    }
+    println!();
+  }
+  if parts.disassembly {
+    for (i, section) in obj.sections.iter().enumerate() {
+      if is_empty(obj, i) {
+        continue;
+      }
+      println!("disassembly of {}:", section.name);
+      for line in Disassembler::from_section(obj, i).lines() {
+        for label in &line.labels {
+          println!("{}:", label);
+        }
+        if !line.text.is_empty() {
+          println!("  {:04x}: {:<12} {}", line.addr, hex(&line), line.text);
+        }
+      }
+      println!();
+    }
+  }
+}
+
+fn to_json(header: Option<Header>, obj: &Obj, parts: &Parts) -> Value {
+  let mut out = json!({});
+  if parts.headers {
+    out["header"] = match header {
+      Some(h) => json!({
+        "version": h.version,
+        "flags": h.flags,
+        "sections": h.sections,
+        "checksum": h.checksum,
+      }),
+      None => Value::Null,
+    };
+    out["sections"] = obj
+      .sections
+      .iter()
+      .map(|s| {
+        json!({
+          "name": s.name,
+          "kind": s.kind.to_string(),
+          "align": s.align,
+          "size": s.data.len(),
+        })
+      })
+      .collect();
+  }
+  if parts.symbols {
+    out["symbols"] = symbols(obj)
+      .into_iter()
+      .map(|(label, section, offset)| {
+        json!({
+          "name": label,
+          "section": section,
+          "offset": offset,
+          "binding": obj.binding(label).unwrap().to_string(),
+        })
+      })
+      .collect();
+    out["externs"] = obj.externs().collect();
+  }
+  if parts.relocs {
+    out["relocations"] = obj
+      .label_uses()
+      .map(|(label, r)| {
+        json!({
+          "section": r.loc.section,
+          "offset": r.loc.offset,
+          "kind": r.kind.to_string(),
+          "label": label,
+          "addend": r.addend,
+        })
+      })
+      .collect();
+  }
+  if parts.disassembly {
+    out["disassembly"] = (0..obj.sections.len())
+      .filter(|i| !is_empty(obj, *i))
+      .map(|i| {
+        let lines: Vec<_> = Disassembler::from_section(obj, i)
+          .lines()
+          .into_iter()
+          .map(|l| {
+            json!({
+              "addr": l.addr,
+              "bytes": l.bytes,
+              "labels": l.labels,
+              "text": l.text,
+            })
+          })
+          .collect();
+        json!({ "section": i, "lines": lines })
+      })
+      .collect();
+  }
+  out
+}
+
+/// the first few bytes of a line, `.skip` can be much longer
+fn hex(line: &Line) -> String {
+  line
+    .bytes
+    .iter()
+    .take(4)
+    .map(|b| format!("{:02x}", b))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+fn print_help() {
+  println!("q16-objdump help:");
+  println!("usage: q16-objdump <input object> [-h] [-t] [-r] [-d] [--json]");
+  println!("-h shows the header and sections, -t the symbols, -r the relocations and -d a disassembly of each section");
+  println!("everything is shown if none are chosen");
+  println!("--json prints the same as a json object, for scripts");
+}
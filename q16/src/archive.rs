@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use crate::{err, assert};
+use crate::obj::{self, Binding, Obj, Reader};
+
+pub const MAGIC: &[u8] = b"q16a";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 8;
+
+/// a bundle of objects, such as a shared runtime, indexed by the global labels each defines
+/// the linker only pulls in the members which resolve labels that are otherwise undefined
+#[derive(Default)]
+pub struct Archive {
+  pub members: Vec<Member>,
+  /// each global or weak label and the member which defines it
+  index: HashMap<String, (usize, Binding)>,
+}
+
+pub struct Member {
+  pub name: String,
+  /// the object file, kept as it was added
+  pub data: Vec<u8>,
+}
+
+impl Archive {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+  }
+
+  /// indexes the global and weak labels of an object and adds it, labels defined globally by more than one member
+  /// are an error
+  pub fn add(&mut self, name: String, data: Vec<u8>) -> Result<(), String> {
+    let obj = Obj::load(&data).map_err(|e| format!("'{}': {}", name, e))?;
+    assert!(
+      !self.members.iter().any(|m| m.name == name),
+      "member '{}' is already in the archive",
+      name
+    )?;
+    for (label, _) in obj.labels() {
+      let binding = obj.binding(label).unwrap();
+      if !binding.is_visible() {
+        continue;
+      }
+      // as when linking, a global definition is preferred over a weak one and the first weak one is kept
+      match (self.index.get(label), binding) {
+        (Some((_, Binding::Weak)), Binding::Global) | (None, _) => {}
+        (Some(_), Binding::Weak) => continue,
+        (Some((i, _)), _) => {
+          return err!("label '{}' is defined by both '{}' and '{}'", label, self.members[*i].name, name);
+        }
+      }
+      self.index.insert(label.to_string(), (self.members.len(), binding));
+    }
+    self.members.push(Member { name, data });
+    Ok(())
+  }
+
+  /// the member which defines a global or weak label
+  pub fn member_defining(&self, label: &str) -> Option<usize> {
+    self.index.get(label).map(|(i, _)| *i)
+  }
+
+  /// each indexed label and the member defining it, in order
+  pub fn symbols(&self) -> Vec<(&str, usize)> {
+    let mut symbols: Vec<_> = self.index.iter().map(|(l, (i, _))| (l.as_str(), *i)).collect();
+    symbols.sort();
+    symbols
+  }
+
+  /// the index is stored rather than rebuilt, and is checked against the members when loaded
+  pub fn load(data: &[u8]) -> Result<Self, String> {
+    assert!(Self::is_archive(data), "invalid magic bytes")?;
+    let mut header = Reader::new(&data[MAGIC.len()..]);
+    let version = header.u16()?;
+    let count = header.u16()?;
+    let checksum = header.u32()?;
+    assert!(
+      version <= VERSION,
+      "unsupported archive version {}, expected at most {}",
+      version,
+      VERSION
+    )?;
+    assert!(
+      obj::crc32(&data[HEADER_LEN..]) == checksum,
+      "checksum mismatch, the archive is corrupt"
+    )?;
+
+    let mut r = Reader::new(&data[HEADER_LEN..]);
+    let index = obj::parse_table::<u16>(&mut r)?;
+    let mut archive = Self::new();
+    for _ in 0..count {
+      let name = r.str()?;
+      let len = r.u32()?;
+      archive.add(name, r.bytes(len as usize)?.to_vec())?;
+    }
+    assert!(r.is_empty(), "trailing bytes after the last member")?;
+    assert!(index.len() == archive.index.len(), "the index doesn't match the members")?;
+    for (label, i) in index {
+      assert!(
+        archive.member_defining(&label) == Some(i as usize),
+        "the index entry for '{}' doesn't match the members",
+        label
+      )?;
+    }
+    Ok(archive)
+  }
+
+  pub fn out(self) -> Vec<u8> {
+    let mut body = vec![];
+    let symbols: Vec<_> = self.symbols().into_iter().map(|(l, i)| (l.to_string(), i as u16)).collect();
+    obj::out_table(&mut body, symbols.into_iter());
+    for m in &self.members {
+      body.extend(m.name.as_bytes());
+      body.push(0);
+      body.extend((m.data.len() as u32).to_le_bytes());
+      body.extend(&m.data);
+    }
+
+    let mut out = Vec::from(MAGIC);
+    out.extend(VERSION.to_le_bytes());
+    out.extend((self.members.len() as u16).to_le_bytes());
+    out.extend(obj::crc32(&body).to_le_bytes());
+    out.extend(body);
+    out
+  }
+}
+
+/// adds each member of the archives which defines a label that is undefined by `objs`, until none are left which an
+/// archive can resolve, earlier archives are searched first
+/// returns the archive and member index of each member added
+pub fn resolve(objs: &mut Vec<Obj>, archives: &[Archive]) -> Result<Vec<(usize, usize)>, String> {
+  let mut added = vec![];
+  loop {
+    let next = undefined(objs).into_iter().find_map(|l| {
+      archives
+        .iter()
+        .enumerate()
+        .find_map(|(a, archive)| Some((a, archive.member_defining(l)?)))
+    });
+    let Some((a, m)) = next else {
+      return Ok(added);
+    };
+    let member = &archives[a].members[m];
+    objs.push(Obj::load(&member.data).map_err(|e| format!("'{}': {}", member.name, e))?);
+    added.push((a, m));
+  }
+}
+
+/// labels which an object needs and no object defines globally, a weak definition is enough to leave archives alone
+fn undefined(objs: &[Obj]) -> Vec<&str> {
+  let mut undefined: Vec<_> = objs
+    .iter()
+    .flat_map(|o| o.undefined())
+    .filter(|l| !objs.iter().any(|o| o.binding(l).is_some_and(Binding::is_visible)))
+    .collect();
+  undefined.sort();
+  undefined.dedup();
+  undefined
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::asm;
+  use crate::link::LinkConfig;
+
+  #[test]
+  fn test_archive() {
+    let mut archive = Archive::new();
+    archive
+      .add("print.o".to_string(), asm::assemble(".extern mul\nprint:\n  jmp mul\n").out_obj())
+      .unwrap();
+    archive
+      .add(
+        "mul.o".to_string(),
+        asm::assemble(".local helper\nmul:\nhelper:\n  jmp helper\n").out_obj(),
+      )
+      .unwrap();
+    archive
+      .add("memcpy.o".to_string(), asm::assemble("memcpy:\n  jmp memcpy\n").out_obj())
+      .unwrap();
+    std::assert!(archive.add("other.o".to_string(), asm::assemble("mul:\n").out_obj()).is_err());
+    // weak definitions never clash, and give way to global ones
+    archive
+      .add(
+        "default.o".to_string(),
+        asm::assemble(".weak memcpy\n.weak fault\nmemcpy:\nfault:\n").out_obj(),
+      )
+      .unwrap();
+    archive.add("fault.o".to_string(), asm::assemble("fault:\n").out_obj()).unwrap();
+    assert_eq!(archive.symbols(), [("fault", 4), ("memcpy", 2), ("mul", 1), ("print", 0)]);
+
+    let bin = archive.out();
+    std::assert!(Archive::load(&bin[..bin.len() - 1]).is_err());
+    let archive = Archive::load(&bin).unwrap();
+    assert_eq!(archive.members[1].name, "mul.o");
+
+    // only the members needed are linked, including those needed by other members
+    let mut objs = vec![asm::assemble(".extern print\nstart:\n  jmp print\n")];
+    assert_eq!(resolve(&mut objs, &[archive]).unwrap(), [(0, 0), (0, 1)]);
+    let mut obj = Obj::new();
+    for o in objs {
+      obj.extend(o).unwrap();
+    }
+    assert_eq!(obj.out_bin(&LinkConfig::default()).unwrap().len(), 12);
+  }
+}
@@ -0,0 +1,964 @@
+use std::iter;
+use std::fmt::Write;
+use std::ops::Range;
+use std::str::FromStr;
+use std::collections::HashMap;
+use crate::{Opcode, Register, Instruction, sts};
+use crate::util::{err, assert, Diagnostic, Severity};
+use crate::obj::{Binding, Loc, Obj, RelocKind, SectionKind, TEXT};
+
+mod parse;
+
+pub use parse::{lex, Token, TokenKind, Spanned, Statement, Arg, Operand, LabelExpr, Part};
+
+pub struct Assembler {
+  pub obj: Obj,
+  /// the most recent global label, which local labels are scoped to
+  scope: Option<String>,
+  /// number of definitions of each numeric label within the current scope
+  numeric_labels: HashMap<String, usize>,
+  /// every label defined in this source, by resolved name
+  labels: HashMap<String, LabelDef>,
+  /// symbols defined with `.equ` or `Assembler::define`, substituted for their value wherever they are used
+  constants: HashMap<String, u16>,
+  /// currently open `.if` blocks, innermost last
+  conds: Vec<Cond>,
+  /// references to local labels, checked once the whole source has been assembled
+  local_uses: Vec<LocalUse>,
+  /// every label operand, kept for tools which navigate the source
+  references: Vec<LabelRef>,
+  /// `.global`, `.local`, `.weak` and `.extern` directives, applied once the whole source has been assembled
+  declarations: Vec<Declaration>,
+  diagnostics: Vec<Diagnostic>,
+  /// the section and range within it that each source line assembled to
+  line_ranges: Vec<(usize, Range<usize>)>,
+  /// the source file named in the object's line table
+  file: String,
+  /// location of the statement currently being assembled
+  line: usize,
+  span: Range<usize>,
+}
+
+struct LabelDef {
+  line: usize,
+  span: Range<usize>,
+  local: bool,
+}
+
+struct Cond {
+  line: usize,
+  span: Range<usize>,
+  /// whether the enclosing block is being assembled
+  parent: bool,
+  value: bool,
+  in_else: bool,
+}
+
+/// a use of a label as an operand, by resolved name
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LabelRef {
+  pub label: String,
+  pub line: usize,
+  pub span: Range<usize>,
+}
+
+struct Declaration {
+  line: usize,
+  span: Range<usize>,
+  label: String,
+  /// `None` for `.extern`
+  binding: Option<Binding>,
+}
+
+struct LocalUse {
+  line: usize,
+  span: Range<usize>,
+  resolved: String,
+  label: String,
+}
+
+impl Default for Assembler {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Assembler {
+  pub fn new() -> Self {
+    Self {
+      obj: Obj::new(),
+      scope: None,
+      numeric_labels: HashMap::new(),
+      labels: HashMap::new(),
+      constants: HashMap::new(),
+      conds: vec![],
+      local_uses: vec![],
+      references: vec![],
+      declarations: vec![],
+      diagnostics: vec![],
+      line_ranges: vec![],
+      file: String::new(),
+      line: 0,
+      span: 0..0,
+    }
+  }
+
+  /// assembles every line, recovering from errors so that all diagnostics are reported at once
+  /// returns `Err` if any of the diagnostics are errors, otherwise just warnings
+  pub fn assemble(&mut self, src: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    for (n, line) in src.lines().enumerate() {
+      self.line = n;
+      let (section, start) = (self.obj.current_section(), self.obj.section().data.len());
+      if let Err(e) = self.assemble_line(line) {
+        self.diagnostics.push(e);
+      }
+      // lines which switch section dont emit anything
+      let end = match self.obj.current_section() == section {
+        true => self.obj.section().data.len(),
+        false => start,
+      };
+      if end > start && self.obj.sections[section].kind == SectionKind::Data {
+        let loc = Loc {
+          section,
+          offset: start as u16,
+        };
+        self.obj.insert_line(&self.file, loc, end - start, n);
+      }
+      self.line_ranges.push((section, start..end));
+    }
+
+    for c in &self.conds {
+      self.diagnostics.push(Diagnostic::error(
+        "unterminated conditional block".to_string(),
+        c.line,
+        c.span.clone(),
+      ));
+    }
+    for u in &self.local_uses {
+      if !self.labels.contains_key(&u.resolved) {
+        self.diagnostics.push(
+          Diagnostic::error(format!("undefined local label '{}'", u.label), u.line, u.span.clone()).note(format!(
+            "resolved to '{}', local labels are scoped to the preceding global label",
+            u.resolved
+          )),
+        );
+      }
+    }
+    self.apply_declarations();
+    for (label, def) in &self.labels {
+      if def.local && !self.local_uses.iter().any(|u| &u.resolved == label) {
+        self.diagnostics.push(Diagnostic::warning(
+          format!("unused local label '{}'", label),
+          def.line,
+          def.span.clone(),
+        ));
+      }
+      // labels declared with `.global` or `.weak` are presumably used by another object
+      let exported = self
+        .declarations
+        .iter()
+        .any(|d| &d.label == label && d.binding.is_some_and(Binding::is_visible));
+      if !def.local && !exported && !self.references.iter().any(|r| &r.label == label) {
+        self.diagnostics.push(
+          Diagnostic::warning(format!("unused label '{}'", label), def.line, def.span.clone())
+            .note("declare it with '.global' if it is used by another object".to_string()),
+        );
+      }
+    }
+
+    let mut diagnostics = std::mem::take(&mut self.diagnostics);
+    diagnostics.sort_by_key(|d| (d.line, d.span.start));
+    match diagnostics.iter().any(|d| d.severity == Severity::Error) {
+      true => Err(diagnostics),
+      false => Ok(diagnostics),
+    }
+  }
+
+  /// sets the binding of declared labels and records externs, then warns about references to labels which are neither
+  fn apply_declarations(&mut self) {
+    for d in &self.declarations {
+      let error = match (d.binding, self.labels.get(&d.label)) {
+        (Some(binding), Some(_)) => {
+          self.obj.set_binding(&d.label, binding);
+          continue;
+        }
+        (Some(_), None) => Diagnostic::error(format!("label '{}' is declared but never defined", d.label), d.line, d.span.clone()),
+        (None, Some(def)) => Diagnostic::error(
+          format!("extern label '{}' is defined in this file", d.label),
+          d.line,
+          d.span.clone(),
+        )
+        .note(format!("defined on line {}", def.line + 1)),
+        (None, None) => {
+          self.obj.insert_extern(d.label.clone());
+          continue;
+        }
+      };
+      self.diagnostics.push(error);
+    }
+
+    let mut warned = vec![];
+    for r in &self.references {
+      // undefined local labels are already errors, as are lines which failed to assemble
+      let local = self.local_uses.iter().any(|u| u.resolved == r.label);
+      let failed = self.diagnostics.iter().any(|d| d.line == r.line && d.severity == Severity::Error);
+      if local || failed || self.labels.contains_key(&r.label) || self.obj.externs().any(|l| l == r.label) || warned.contains(&&r.label) {
+        continue;
+      }
+      warned.push(&r.label);
+      self.diagnostics.push(
+        Diagnostic::warning(format!("undefined label '{}'", r.label), r.line, r.span.clone())
+          .note("declare it with '.extern' if it is defined in another object".to_string()),
+      );
+    }
+  }
+
+  /// names the source in the line table of the object, for debugging
+  pub fn set_file(&mut self, file: &str) {
+    self.file = file.to_string();
+  }
+
+  /// defines a constant as if by `.equ`, eg from the command line
+  pub fn define(&mut self, name: &str, value: &str) -> Result<(), String> {
+    match self.resolve_operand(Operand::parse(value)?) {
+      Operand::Literal(x) => self.define_constant(name, x),
+      _ => err!("invalid value '{}' for '{}'", value, name),
+    }
+  }
+
+  /// the address, encoded bytes and source of each line of `src` side by side, followed by a symbol table
+  /// addresses are relative to the start of their section and label references are shown unrelocated
+  pub fn listing(&self, src: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "addr  bytes          line  source").unwrap();
+    for (n, (line, (section, range))) in src.lines().zip(&self.line_ranges).enumerate() {
+      let data = &self.obj.sections[*section].data[range.clone()];
+      let mut rows = data.chunks(LISTING_ROW_LEN).take(LISTING_MAX_ROWS);
+      let first = rows.next().unwrap_or_default();
+      writeln!(out, "{:04x}  {:<13}  {:>4}  {}", range.start, hex_bytes(first), n + 1, line).unwrap();
+      for (i, row) in rows.enumerate() {
+        writeln!(out, "{:04x}  {}", range.start + (i + 1) * LISTING_ROW_LEN, hex_bytes(row)).unwrap();
+      }
+      if data.len() > LISTING_ROW_LEN * LISTING_MAX_ROWS {
+        writeln!(out, "      ...").unwrap();
+      }
+    }
+
+    writeln!(out, "\nsymbols:").unwrap();
+    let mut labels: Vec<_> = self.obj.labels().collect();
+    labels.sort_by_key(|(label, loc)| (*loc, *label));
+    for (label, loc) in labels {
+      match &self.obj.sections[loc.section].name {
+        name if name == TEXT => writeln!(out, "{:04x}  {}", loc.offset, label).unwrap(),
+        name => writeln!(out, "{:04x}  {} ({})", loc.offset, label, name).unwrap(),
+      }
+    }
+    let mut constants: Vec<_> = self.constants.iter().collect();
+    constants.sort();
+    for (name, x) in constants {
+      writeln!(out, "{:04x}  {} (constant)", x, name).unwrap();
+    }
+    let mut undefined: Vec<_> = self.obj.label_uses().map(|(l, _)| l).filter(|l| !self.obj.has_label(l)).collect();
+    undefined.sort();
+    undefined.dedup();
+    let mut externs: Vec<_> = self.obj.externs().collect();
+    externs.sort();
+    for label in externs {
+      writeln!(out, "----  {} (extern)", label).unwrap();
+    }
+    for label in undefined.into_iter().filter(|l| !self.obj.externs().any(|e| e == *l)) {
+      writeln!(out, "----  {} (undefined)", label).unwrap();
+    }
+    out
+  }
+
+  /// the line and span of every label definition, by resolved name
+  pub fn definitions(&self) -> impl Iterator<Item = (&str, usize, Range<usize>)> {
+    self.labels.iter().map(|(l, def)| (l.as_str(), def.line, def.span.clone()))
+  }
+
+  pub fn references(&self) -> &[LabelRef] {
+    &self.references
+  }
+
+  /// the bytes a line of source assembled to
+  pub fn line_bytes(&self, line: usize) -> &[u8] {
+    match self.line_ranges.get(line) {
+      Some((section, range)) => &self.obj.sections[*section].data[range.clone()],
+      None => &[],
+    }
+  }
+
+  fn assemble_line(&mut self, line: &str) -> Result<(), Diagnostic> {
+    let stmt = Statement::parse(line);
+    if stmt.code.text.is_empty() {
+      return Ok(());
+    }
+    self.span = stmt.code.span.clone();
+
+    let mnemonic = stmt.mnemonic.map(|m| m.text.to_lowercase()).unwrap_or_default();
+    if matches!(mnemonic.as_str(), ".if" | ".ifdef" | ".ifndef" | ".else" | ".endif") {
+      return self
+        .assemble_cond(
+          &mnemonic,
+          stmt.operands.first().map_or(err!("empty operand"), |op| op.value.clone()),
+        )
+        .map_err(|e| Diagnostic::error(e, self.line, self.span.clone()));
+    }
+    if !self.active() {
+      return Ok(());
+    }
+
+    if let Some(label) = stmt.label {
+      return self.define_label(label.text, label.span);
+    }
+
+    let mut parsed = vec![];
+    for (i, op) in stmt.operands.iter().enumerate() {
+      let operand = op.value.clone().map_err(|e| Diagnostic::error(e, self.line, op.span.clone()))?;
+      if let Some(x) = self.overflow(operand) {
+        self.diagnostics.push(Diagnostic::warning(
+          format!("'{}' overflows 16 bits, and wraps to 0x{:x}", op.text, x),
+          self.line,
+          op.span.clone(),
+        ));
+      }
+      // the name being defined by `.equ`, of a section or being declared is not substituted
+      let named = matches!(mnemonic.as_str(), ".equ" | ".section" | ".global" | ".local" | ".weak" | ".extern") && i == 0;
+      let operand = match named {
+        true => operand,
+        false => self.resolve_operand(operand),
+      };
+      if let (Operand::Label(l), false) = (operand, named || mnemonic == ".equ") {
+        if let Ok(label) = self.resolve_label(l.label) {
+          // just the label within an expression such as `hi(label + 1)`
+          let start = op.span.start + l.offset;
+          self.references.push(LabelRef {
+            label,
+            line: self.line,
+            span: start..start + l.label.len(),
+          });
+        }
+      }
+      parsed.push(operand);
+    }
+    self
+      .assemble_instr(&mnemonic, parsed)
+      .map_err(|e| Diagnostic::error(e, self.line, self.span.clone()))
+  }
+
+  /// whether lines are currently being assembled, ie not within a false conditional block
+  fn active(&self) -> bool {
+    self.conds.last().is_none_or(|c| c.parent && c.value != c.in_else)
+  }
+
+  fn assemble_cond(&mut self, directive: &str, operand: Result<Operand, String>) -> Result<(), String> {
+    match directive {
+      ".else" => match self.conds.last_mut() {
+        Some(c) if !c.in_else => {
+          c.in_else = true;
+          Ok(())
+        }
+        Some(_) => err!("duplicate '.else'"),
+        None => err!("'.else' without matching '.if'"),
+      },
+      ".endif" => match self.conds.pop() {
+        Some(_) => Ok(()),
+        None => err!("'.endif' without matching '.if'"),
+      },
+      _ => {
+        let parent = self.active();
+        // conditions within inactive blocks may refer to undefined symbols, so arent evaluated
+        let value = match parent {
+          true => self.eval_cond(directive, operand),
+          false => Ok(false),
+        };
+        // the block is still opened on error so that its '.endif' matches
+        self.conds.push(Cond {
+          line: self.line,
+          span: self.span.clone(),
+          parent,
+          value: *value.as_ref().unwrap_or(&false),
+          in_else: false,
+        });
+        value.map(|_| ())
+      }
+    }
+  }
+
+  fn eval_cond(&self, directive: &str, operand: Result<Operand, String>) -> Result<bool, String> {
+    match (directive, operand?) {
+      (".ifdef", Operand::Label(l)) => Ok(self.constants.contains_key(l.label) || self.labels.contains_key(l.label)),
+      (".ifndef", Operand::Label(l)) => Ok(!self.constants.contains_key(l.label) && !self.labels.contains_key(l.label)),
+      (".if", op) => match self.resolve_operand(op) {
+        Operand::Literal(x) => Ok(x != 0),
+        Operand::Label(l) => err!("undefined constant '{}'", l.label),
+        _ => err!("invalid operand for '.if'"),
+      },
+      _ => err!("invalid operand for '{}'", directive),
+    }
+  }
+
+  /// substitutes constants for their value, taking the same part of it as would be taken of an address
+  fn resolve_operand<'a>(&self, operand: Operand<'a>) -> Operand<'a> {
+    match operand {
+      Operand::Label(l) => match self.constants.get(l.label).map(|x| x.wrapping_add(l.addend as u16)) {
+        Some(x) => Operand::Literal(match l.part {
+          Part::Addr => x,
+          Part::Hi => x >> 8,
+          Part::Lo => x & 0xff,
+        }),
+        None => operand,
+      },
+      _ => operand,
+    }
+  }
+
+  /// the wrapped value of a constant plus an addend which is outside of 0..=0xffff
+  fn overflow(&self, operand: Operand) -> Option<u16> {
+    let Operand::Label(l) = operand else {
+      return None;
+    };
+    let x = *self.constants.get(l.label)? as i32 + l.addend as i32;
+    (x > u16::MAX as i32 || x < 0).then_some(x as u16)
+  }
+
+  fn define_constant(&mut self, name: &str, x: u16) -> Result<(), String> {
+    assert!(!self.labels.contains_key(name), "'{}' is already defined as a label", name)?;
+    match self.constants.try_insert(name.to_string(), x) {
+      Ok(_) => Ok(()),
+      Err(_) => err!("constant '{}' already defined", name),
+    }
+  }
+
+  fn assemble_instr(&mut self, mnemonic: &str, operands: Vec<Operand>) -> Result<(), String> {
+    let section = self.obj.section();
+    if section.kind == SectionKind::Zero
+      && !matches!(
+        mnemonic,
+        ".skip" | ".align" | ".org" | ".equ" | ".section" | ".text" | ".data" | ".bss" | ".global" | ".local" | ".weak" | ".extern"
+      )
+    {
+      return err!("'{}' is not allowed in zero filled section '{}'", mnemonic, section.name);
+    }
+    match Opcode::from_str(mnemonic) {
+      Ok(opc @ (Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::Rem | Opcode::And | Opcode::Or | Opcode::Xor)) => {
+        assert_len(mnemonic, &operands, 3)?;
+        match operands[2] {
+          Operand::Literal(l) if opc == Opcode::Sub => {
+            self.assemble_3(Opcode::Add, &[operands[0], operands[1], Operand::Literal(negate(l))])
+          }
+          _ => self.assemble_3(opc, &operands),
+        }
+      }
+      Ok(opc @ (Opcode::Lb | Opcode::Lbu | Opcode::Lw | Opcode::Sb | Opcode::Sw)) => {
+        if operands.len() == 3 {
+          self.assemble_3(opc, &operands)
+        } else if operands.len() == 2 {
+          self.assemble_2(opc, &operands)
+        } else {
+          err!("'{}' requires 2 or 3 operands, found {}", mnemonic, operands.len())
+        }
+      }
+      Ok(opc @ (Opcode::Jeq | Opcode::Jne | Opcode::Jgt | Opcode::Jlt | Opcode::Jge | Opcode::Jle)) => {
+        // the destination register is unused but is accepted so that every encoding can be written
+        if operands.len() == 3 {
+          self.assemble_3(opc, &operands)
+        } else if operands.len() == 2 {
+          self.assemble_3(opc, &[Operand::Register(Register::R0), operands[0], operands[1]])
+        } else if operands.len() == 1 {
+          self.assemble_1(opc, operands[0])
+        } else {
+          err!("'{}' requires 1 to 3 operands, found {}", mnemonic, operands.len())
+        }
+      }
+      Err(_) => match mnemonic {
+        "nop" => {
+          self
+            .obj
+            .emit_instr(Instruction::R(Opcode::Add, Register::R0, Register::R0, Register::R0));
+          Ok(())
+        }
+        "hlt" => {
+          self
+            .obj
+            .emit_instr(Instruction::I(Opcode::And, Register::STS, Register::STS, !(1 << sts::RUN)));
+          Ok(())
+        }
+        "mov" => {
+          assert_len("mov", &operands, 2)?;
+          self.assemble_2(Opcode::Add, &operands)
+        }
+        "neg" => {
+          assert_len("neg", &operands, 2)?;
+          self.assemble_3(Opcode::Sub, &[operands[0], Operand::Register(Register::R0), operands[1]])
+        }
+        "not" => {
+          assert_len("not", &operands, 2)?;
+          self.assemble_3(Opcode::Xor, &[operands[0], operands[1], Operand::Literal(u16::MAX)])
+        }
+        "cmp" => {
+          assert_len("cmp", &operands, 2)?;
+          match operands[1] {
+            Operand::Literal(l) => self.assemble_3(
+              Opcode::Add,
+              &[Operand::Register(Register::R0), operands[0], Operand::Literal(negate(l))],
+            ),
+            _ => self.assemble_3(Opcode::Sub, &[Operand::Register(Register::R0), operands[0], operands[1]]),
+          }
+        }
+        "jmp" => {
+          if operands.len() == 2 {
+            self.assemble_3(Opcode::Add, &[Operand::Register(Register::PC), operands[0], operands[1]])
+          } else if operands.len() == 1 {
+            self.assemble_2(Opcode::Add, &[Operand::Register(Register::PC), operands[0]])
+          } else {
+            err!("'jmp' requires 1 or 2 operands, found {}", operands.len())
+          }
+        }
+        // pc relative branches, which unlike jumps dont depend on where the code is loaded
+        "br" => {
+          assert_len("br", &operands, 1)?;
+          self.assemble_3(
+            Opcode::Add,
+            &[Operand::Register(Register::PC), Operand::Register(Register::PC), operands[0]],
+          )
+        }
+        "beq" | "bne" | "bgt" | "blt" | "bge" | "ble" => {
+          assert_len(mnemonic, &operands, 1)?;
+          let opc = Opcode::from_str(&mnemonic.replacen('b', "j", 1)).unwrap();
+          self.assemble_3(
+            opc,
+            &[Operand::Register(Register::R0), Operand::Register(Register::PC), operands[0]],
+          )
+        }
+        // `li` and `la` both load into a register but distinguish between values and addresses
+        "li" => {
+          assert_len("li", &operands, 2)?;
+          match operands[1] {
+            Operand::Literal(_) => self.assemble_2(Opcode::Add, &operands),
+            Operand::Label(l) => err!("'li' requires an immediate, use 'la' to load the address of '{}'", l.label),
+            _ => err!("invalid operands for 'li'"),
+          }
+        }
+        "la" => {
+          assert_len("la", &operands, 2)?;
+          match operands[1] {
+            Operand::Label(_) => self.assemble_2(Opcode::Add, &operands),
+            Operand::Literal(_) => err!("'la' requires a label, use 'li' to load an immediate"),
+            _ => err!("invalid operands for 'la'"),
+          }
+        }
+        "inc" => {
+          assert_len("inc", &operands, 1)?;
+          self.assemble_3(Opcode::Add, &[operands[0], operands[0], Operand::Literal(1)])
+        }
+        ".db" => {
+          assert_len(".db", &operands, 1)?;
+          match operands[0] {
+            Operand::Literal(x) => self.obj.section().data.push(to_byte(x)?),
+            Operand::Label(l) => {
+              self.insert_label_usage(l, 0, RelocKind::Byte)?;
+              self.obj.section().data.push(0);
+            }
+            _ => return err!("invalid operand for '.db'."),
+          }
+          Ok(())
+        }
+        ".dw" => {
+          assert_len(".dw", &operands, 1)?;
+          match operands[0] {
+            Operand::Literal(x) => self.obj.section().data.extend(x.to_le_bytes()),
+            Operand::Label(l) => {
+              self.insert_label_usage(l, 0, RelocKind::Abs)?;
+              self.obj.section().data.extend([0, 0]);
+            }
+            _ => return err!("invalid operand for '.dw'."),
+          }
+          Ok(())
+        }
+        ".equ" => {
+          assert_len(".equ", &operands, 2)?;
+          match operands[0..2] {
+            [Operand::Label(name), Operand::Literal(x)] => match name.name() {
+              Some(name) => self.define_constant(name, x),
+              None => err!("invalid name for '.equ'"),
+            },
+            _ => err!("invalid operands for '.equ'."),
+          }
+        }
+        ".skip" => {
+          assert_len(".skip", &operands, 1)?;
+          match operands[0] {
+            Operand::Literal(n) => self.obj.section().data.extend(iter::repeat_n(0, n as _)),
+            _ => return err!("invalid operand for '.skip'."),
+          }
+          Ok(())
+        }
+        ".align" => {
+          assert_len(".align", &operands, 1)?;
+          match operands[0] {
+            Operand::Literal(n) if n.is_power_of_two() => self.obj.align(n),
+            Operand::Literal(_) => return err!("alignment must be a power of two"),
+            _ => return err!("invalid operand for '.align'."),
+          }
+          Ok(())
+        }
+        ".org" => {
+          assert_len(".org", &operands, 1)?;
+          match operands[0] {
+            Operand::Literal(addr) if addr as usize >= self.obj.section().data.len() => self.obj.section().data.resize(addr as _, 0),
+            Operand::Literal(addr) => {
+              return err!(
+                "'.org' cannot move backwards to 0x{:x}, already at 0x{:x}",
+                addr,
+                self.obj.section().data.len()
+              )
+            }
+            _ => return err!("invalid operand for '.org'."),
+          }
+          Ok(())
+        }
+        ".text" | ".data" | ".bss" => {
+          assert_len(mnemonic, &operands, 0)?;
+          self.obj.switch_section(mnemonic)
+        }
+        ".section" => {
+          assert_len(".section", &operands, 1)?;
+          match operands[0] {
+            Operand::Label(name) => match name.name() {
+              Some(name) => self.obj.switch_section(name),
+              None => err!("invalid section name"),
+            },
+            _ => err!("invalid operand for '.section'."),
+          }
+        }
+        ".global" | ".local" | ".weak" | ".extern" => {
+          assert_len(mnemonic, &operands, 1)?;
+          let label = match operands[0] {
+            Operand::Label(l) if l.name().is_some() => l.label,
+            _ => return err!("invalid operand for '{}'.", mnemonic),
+          };
+          check_label_name(label)?;
+          assert!(
+            !label.starts_with('.') && !is_numeric(label),
+            "local label '{}' is scoped to its global label and cannot be declared",
+            label
+          )?;
+          self.declarations.push(Declaration {
+            line: self.line,
+            span: self.span.clone(),
+            label: label.to_string(),
+            binding: match mnemonic {
+              ".global" => Some(Binding::Global),
+              ".local" => Some(Binding::Local),
+              ".weak" => Some(Binding::Weak),
+              _ => None,
+            },
+          });
+          Ok(())
+        }
+        ".fill" => {
+          assert_len(".fill", &operands, 2)?;
+          match operands[0..2] {
+            [Operand::Literal(n), Operand::Literal(x)] => self.obj.section().data.extend(iter::repeat_n(to_byte(x)?, n as _)),
+            _ => return err!("invalid operands for '.fill'."),
+          }
+          Ok(())
+        }
+        _ => err!("unknown mnemonic '{}'", mnemonic),
+      },
+    }
+  }
+
+  /// accepts %rd, %r2, %r1/imm/label, requires operands.len() == 3
+  /// labels are encoded relative to %pc when it is %r2
+  fn assemble_3(&mut self, opcode: Opcode, operands: &[Operand]) -> Result<(), String> {
+    let instr = match operands[0..3] {
+      [Operand::Register(rd), Operand::Register(r1), Operand::Register(r2)] => Instruction::R(opcode, rd, r1, r2),
+      [Operand::Register(rd), Operand::Register(r1), Operand::Literal(imm)] => Instruction::I(opcode, rd, r1, imm),
+      [Operand::Register(rd), Operand::Register(r1), Operand::Label(l)] => {
+        let kind = match r1 {
+          Register::PC => RelocKind::PcRel,
+          _ => RelocKind::Abs,
+        };
+        self.insert_label_usage(l, 2, kind)?;
+        Instruction::I(opcode, rd, r1, 0)
+      }
+      _ => return err!("invalid operands for '{}'", opcode),
+    };
+    self.obj.emit_instr(instr);
+    Ok(())
+  }
+
+  /// accepts %rd, %r1/imm/label, requires operands.len() == 2
+  fn assemble_2(&mut self, opcode: Opcode, operands: &[Operand]) -> Result<(), String> {
+    let instr = match operands[0..2] {
+      [Operand::Register(rd), Operand::Register(r1)] => Instruction::I(opcode, rd, r1, 0),
+      [Operand::Register(rd), Operand::Literal(imm)] => Instruction::I(opcode, rd, Register::R0, imm),
+      [Operand::Register(rd), Operand::Label(l)] => {
+        self.insert_label_usage(l, 2, RelocKind::Abs)?;
+        Instruction::I(opcode, rd, Register::R0, 0)
+      }
+      _ => return err!("invalid operands for '{}'", opcode),
+    };
+    self.obj.emit_instr(instr);
+    Ok(())
+  }
+
+  /// accepts %r1/imm/label
+  fn assemble_1(&mut self, opcode: Opcode, operand: Operand) -> Result<(), String> {
+    let instr = match operand {
+      Operand::Register(r1) => Instruction::I(opcode, Register::R0, r1, 0),
+      Operand::Literal(imm) => Instruction::I(opcode, Register::R0, Register::R0, imm),
+      Operand::Label(l) => {
+        self.insert_label_usage(l, 2, RelocKind::Abs)?;
+        Instruction::I(opcode, Register::R0, Register::R0, 0)
+      }
+    };
+    self.obj.emit_instr(instr);
+    Ok(())
+  }
+
+  /// local labels (`.name` or numeric) are qualified with the enclosing global label, eg `.loop` in `main` becomes `main.loop`
+  fn define_label(&mut self, label: &str, span: Range<usize>) -> Result<(), Diagnostic> {
+    if let Err(e) = check_label_name(label) {
+      return Err(Diagnostic::error(e, self.line, span));
+    }
+    let local = label.starts_with('.') || is_numeric(label);
+    let resolved = if local {
+      let scope = self.scope(label).map_err(|e| Diagnostic::error(e, self.line, span.clone()))?;
+      if is_numeric(label) {
+        let count = self.numeric_labels.entry(label.to_string()).or_insert(0);
+        *count += 1;
+        format!("{}.{}#{}", scope, label, *count - 1)
+      } else {
+        format!("{}{}", scope, label)
+      }
+    } else if label.is_empty() || label.starts_with(|c: char| c.is_ascii_digit()) {
+      return Err(Diagnostic::error(format!("invalid label name '{}'", label), self.line, span));
+    } else if self.constants.contains_key(label) {
+      return Err(Diagnostic::error(
+        format!("'{}' is already defined as a constant", label),
+        self.line,
+        span,
+      ));
+    } else {
+      self.scope = Some(label.to_string());
+      self.numeric_labels.clear();
+      label.to_string()
+    };
+
+    if let Some(prev) = self.labels.get(&resolved) {
+      return Err(
+        Diagnostic::error(format!("label '{}' already declared", resolved), self.line, span)
+          .note(format!("previously declared on line {}", prev.line + 1)),
+      );
+    }
+    self
+      .obj
+      .insert_label(resolved.clone(), if local { Binding::Local } else { Binding::Global })
+      .map_err(|e| Diagnostic::error(e, self.line, span.clone()))?;
+    self.labels.insert(
+      resolved,
+      LabelDef {
+        line: self.line,
+        span,
+        local,
+      },
+    );
+    Ok(())
+  }
+
+  /// `kind` is how the address is used, which is adjusted for the part of it the expression takes
+  fn insert_label_usage(&mut self, expr: LabelExpr, offset: usize, kind: RelocKind) -> Result<(), String> {
+    let kind = match (expr.part, kind) {
+      (Part::Addr, kind) => kind,
+      (Part::Hi, RelocKind::Byte) => RelocKind::HiByte,
+      (Part::Lo, RelocKind::Byte) => RelocKind::LoByte,
+      (Part::Hi, RelocKind::Abs) => RelocKind::Hi,
+      (Part::Lo, RelocKind::Abs) => RelocKind::Lo,
+      _ => return err!("part of the address of '{}' cannot be relative to %pc", expr.label),
+    };
+    let label = expr.label;
+    let resolved = self.resolve_label(label)?;
+    if resolved != label {
+      self.local_uses.push(LocalUse {
+        line: self.line,
+        span: self.span.clone(),
+        resolved: resolved.clone(),
+        label: label.to_string(),
+      });
+    }
+    self.obj.insert_label_usage(resolved, offset, kind, expr.addend);
+    Ok(())
+  }
+
+  /// qualifies a reference to a local label with its scope
+  /// numeric labels are referenced as `1b`/`1f`, meaning the nearest definition of `1:` backwards/forwards
+  fn resolve_label(&self, label: &str) -> Result<String, String> {
+    if let Some(n) = label.strip_suffix(['b', 'f']).filter(|n| is_numeric(n)) {
+      let scope = self.scope(label)?;
+      let count = self.numeric_labels.get(n).copied().unwrap_or(0);
+      let idx = match label.ends_with('b') {
+        true if count == 0 => return err!("no previous definition of local label '{}'", n),
+        true => count - 1,
+        false => count,
+      };
+      Ok(format!("{}.{}#{}", scope, n, idx))
+    } else if label.starts_with('.') {
+      Ok(format!("{}{}", self.scope(label)?, label))
+    } else {
+      Ok(label.to_string())
+    }
+  }
+
+  fn scope(&self, label: &str) -> Result<String, String> {
+    match &self.scope {
+      Some(s) => Ok(s.clone()),
+      None => err!("local label '{}' must follow a global label", label),
+    }
+  }
+}
+
+fn assert_len(mnemonic: &str, operands: &[Operand], expect: usize) -> Result<(), String> {
+  assert!(
+    operands.len() == expect,
+    "'{}' requires {} operands, found {}",
+    mnemonic,
+    expect,
+    operands.len()
+  )
+}
+
+fn negate(x: u16) -> u16 {
+  x.wrapping_neg()
+}
+
+/// accepts both signed and unsigned bytes
+fn to_byte(x: u16) -> Result<u8, String> {
+  match x <= u8::MAX as u16 || x >= i8::MIN as u16 {
+    true => Ok(x as u8),
+    false => err!("immediate 0x{:x} out of range for a byte ({}..{})", x, i8::MIN, u8::MAX),
+  }
+}
+
+const LISTING_ROW_LEN: usize = 4;
+const LISTING_MAX_ROWS: usize = 4;
+
+fn hex_bytes(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+fn is_numeric(s: &str) -> bool {
+  !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// `:` ends a label and whitespace separates operands, so neither can appear in one
+fn check_label_name(label: &str) -> Result<(), String> {
+  assert!(
+    !label.contains(|c: char| c == ':' || c.is_whitespace()),
+    "invalid label name '{}', labels cannot contain ':' or whitespace",
+    label
+  )
+}
+
+/// the object assembled from `src`, which must not have any errors
+#[cfg(test)]
+pub(crate) fn assemble(src: &str) -> Obj {
+  assemble_file("", src)
+}
+
+/// as `assemble`, with lines attributed to `file`
+#[cfg(test)]
+pub(crate) fn assemble_file(file: &str, src: &str) -> Obj {
+  let mut assembler = Assembler::new();
+  assembler.set_file(file);
+  assembler.assemble(src).unwrap();
+  assembler.obj
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_collects_diagnostics() {
+    let mut assembler = Assembler::new();
+    let diagnostics = assembler
+      .assemble("main:\n  add %r9, %r1, 0\n  .db 300\n  mov %r1, 0x4g ; comment\n.unused:\n")
+      .unwrap_err();
+    let found: Vec<_> = diagnostics.iter().map(|d| (d.severity, d.line, d.span.clone())).collect();
+    assert_eq!(
+      found,
+      [
+        (Severity::Warning, 0, 0..4),
+        (Severity::Error, 1, 6..9),
+        (Severity::Error, 2, 2..9),
+        (Severity::Error, 3, 11..15),
+        (Severity::Warning, 4, 0..7),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_defines() {
+    let mut assembler = Assembler::new();
+    assembler.define("SIZE", "0x10").unwrap();
+    assembler.define("VERBOSE", "1").unwrap();
+    std::assert!(assembler.define("SIZE", "2").is_err());
+    std::assert!(assembler.define("OTHER", "%r1").is_err());
+    assembler
+      .assemble(".ifdef VERBOSE\n.if SIZE\n.db SIZE\n.endif\n.else\n.db 0\n.endif\n")
+      .unwrap();
+    assert_eq!(assembler.obj.sections[0].data, [0x10]);
+  }
+
+  #[test]
+  fn test_warnings() {
+    let mut assembler = Assembler::new();
+    let src = ".global start\n.equ TOP, 0xfff0\n.equ Z, 0\nstart:\n  mov %r1, TOP + 0x20\n  mov %r2, TOP + 0xf\n  mov %r3, Z - 1\n  jmp used\nused:\nunused:\n";
+    let diagnostics = assembler.assemble(src).unwrap();
+    let found: Vec<_> = diagnostics.iter().map(|d| (d.line, d.msg.as_str())).collect();
+    assert_eq!(
+      found,
+      [
+        (4, "'TOP + 0x20' overflows 16 bits, and wraps to 0x10"),
+        (6, "'Z - 1' overflows 16 bits, and wraps to 0xffff"),
+        (9, "unused label 'unused'"),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_label_names() {
+    for src in ["x:1:\n", "two words:\n", ".global x:1\n"] {
+      let diagnostics = Assembler::new().assemble(src).unwrap_err();
+      std::assert!(diagnostics[0].msg.starts_with("invalid label name"));
+    }
+  }
+
+  #[test]
+  fn test_label_references() {
+    let mut assembler = Assembler::new();
+    assembler
+      .assemble("main:\n  jmp .loop\n.loop:\n  la %r1, main\n  beq .loop\n")
+      .unwrap();
+    let refs: Vec<_> = assembler
+      .references()
+      .iter()
+      .map(|r| (r.label.as_str(), r.line, r.span.clone()))
+      .collect();
+    assert_eq!(refs, [("main.loop", 1, 6..11), ("main", 3, 10..14), ("main.loop", 4, 6..11)]);
+    let mut defs: Vec<_> = assembler.definitions().collect();
+    defs.sort_by_key(|(l, _, _)| *l);
+    assert_eq!(defs, [("main", 0, 0..4), ("main.loop", 2, 0..5)]);
+    assert_eq!(assembler.line_bytes(1).len(), 4);
+
+    // the label within an expression, rather than the first match of its name
+    let mut assembler = Assembler::new();
+    assembler.assemble("h:\nl:\n  mov %r1, hi(h)\n  mov %r2, lo(l)\n").unwrap();
+    let spans: Vec<_> = assembler.references().iter().map(|r| (r.line, r.span.clone())).collect();
+    assert_eq!(spans, [(2, 14..15), (3, 14..15)]);
+  }
+}
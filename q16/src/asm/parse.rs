@@ -0,0 +1,417 @@
+use std::ops::Range;
+use std::str::FromStr;
+use crate::Register;
+use crate::util::{err, assert};
+use super::negate;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+  /// mnemonics, directives, labels and constants, which are told apart by where they appear in a statement
+  Ident,
+  Register,
+  Number,
+  Char,
+  String,
+  Comma,
+  Colon,
+  /// from the `;` to the end of the line
+  Comment,
+  /// an unterminated literal
+  Error,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Token {
+  pub kind: TokenKind,
+  /// byte range within the line
+  pub span: Range<usize>,
+}
+
+/// splits a line of source into tokens, skipping whitespace
+pub fn lex(line: &str) -> Vec<Token> {
+  let bytes = line.as_bytes();
+  let mut tokens = vec![];
+  let mut pos = 0;
+  while pos < bytes.len() {
+    let start = pos;
+    let kind = match bytes[pos] {
+      b if b.is_ascii_whitespace() => {
+        pos += 1;
+        continue;
+      }
+      b';' => {
+        pos = bytes.len();
+        TokenKind::Comment
+      }
+      b',' => {
+        pos += 1;
+        TokenKind::Comma
+      }
+      b':' => {
+        pos += 1;
+        TokenKind::Colon
+      }
+      b'-' if bytes.get(pos + 1) == Some(&b'\'') => lex_quoted(bytes, &mut pos, 1, TokenKind::Char),
+      b'\'' => lex_quoted(bytes, &mut pos, 0, TokenKind::Char),
+      b'"' => lex_quoted(bytes, &mut pos, 0, TokenKind::String),
+      b => {
+        pos += 1;
+        while pos < bytes.len() && is_ident_byte(bytes[pos]) {
+          pos += 1;
+        }
+        let text = &line[start..pos];
+        match b {
+          b'%' => TokenKind::Register,
+          // `1b` and `1f` refer to numeric labels
+          _ if is_label_ref(text) => TokenKind::Ident,
+          b'0'..=b'9' => TokenKind::Number,
+          b'-' if bytes.get(start + 1).is_some_and(u8::is_ascii_digit) => TokenKind::Number,
+          _ => TokenKind::Ident,
+        }
+      }
+    };
+    tokens.push(Token { kind, span: start..pos });
+  }
+  tokens
+}
+
+/// skips `prefix` bytes then a literal delimited by its first byte, which may contain escaped delimiters
+fn lex_quoted(bytes: &[u8], pos: &mut usize, prefix: usize, kind: TokenKind) -> TokenKind {
+  *pos += prefix;
+  let delim = bytes[*pos];
+  *pos += 1;
+  while *pos < bytes.len() {
+    match bytes[*pos] {
+      b'\\' => *pos += 2,
+      b if b == delim => {
+        *pos += 1;
+        return kind;
+      }
+      _ => *pos += 1,
+    }
+  }
+  *pos = bytes.len();
+  TokenKind::Error
+}
+
+fn is_ident_byte(b: u8) -> bool {
+  !b.is_ascii_whitespace() && !matches!(b, b',' | b';' | b':' | b'\'' | b'"')
+}
+
+fn is_label_ref(s: &str) -> bool {
+  s.len() > 1 && s.ends_with(['b', 'f']) && s[..s.len() - 1].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// a part of a line of source
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Spanned<'a> {
+  pub text: &'a str,
+  /// byte range of `text` within the line
+  pub span: Range<usize>,
+}
+
+impl<'a> Spanned<'a> {
+  fn new(line: &'a str, span: Range<usize>) -> Self {
+    Self {
+      text: &line[span.clone()],
+      span,
+    }
+  }
+}
+
+/// an operand as written, along with what it parses to
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Arg<'a> {
+  pub text: &'a str,
+  /// byte range of `text` within the line
+  pub span: Range<usize>,
+  /// why it couldn't be parsed, which is only reported if the statement is assembled
+  pub value: Result<Operand<'a>, String>,
+}
+
+impl<'a> Arg<'a> {
+  fn new(line: &'a str, span: Range<usize>) -> Self {
+    let text = &line[span.clone()];
+    Self {
+      text,
+      span,
+      value: Operand::parse(text),
+    }
+  }
+}
+
+/// a line of source split into its parts
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Statement<'a> {
+  /// the line without its comment or surrounding whitespace
+  pub code: Spanned<'a>,
+  /// labels are on their own line, so a statement has either a label or a mnemonic
+  pub label: Option<Spanned<'a>>,
+  pub mnemonic: Option<Spanned<'a>>,
+  pub operands: Vec<Arg<'a>>,
+  /// everything after the `;`
+  pub comment: Option<Spanned<'a>>,
+}
+
+impl<'a> Statement<'a> {
+  pub fn parse(line: &'a str) -> Self {
+    let mut tokens = lex(line);
+    let mut stmt = Self::default();
+    if let Some(t) = tokens.pop_if(|t| t.kind == TokenKind::Comment) {
+      stmt.comment = Some(Spanned::new(line, t.span.start + 1..t.span.end));
+    }
+    let (Some(first), Some(last)) = (tokens.first(), tokens.last()) else {
+      let end = stmt.comment.as_ref().map_or(0, |c| c.span.start - 1);
+      stmt.code = Spanned::new(line, end..end);
+      return stmt;
+    };
+    stmt.code = Spanned::new(line, first.span.start..last.span.end);
+
+    if last.kind == TokenKind::Colon {
+      let text = line[first.span.start..last.span.start].trim_end();
+      stmt.label = Some(Spanned::new(line, first.span.start..first.span.start + text.len()));
+      return stmt;
+    }
+    stmt.mnemonic = Some(Spanned::new(line, first.span.clone()));
+    // operands are separated by commas, a trailing comma is allowed
+    let mut start = 1;
+    for i in 1..=tokens.len() {
+      let end = tokens.get(i).is_none_or(|t| t.kind == TokenKind::Comma);
+      if !end || (i == tokens.len() && start == i) {
+        continue;
+      }
+      let span = match start == i {
+        true => tokens[i - 1].span.end..tokens[i - 1].span.end,
+        false => tokens[start].span.start..tokens[i - 1].span.end,
+      };
+      stmt.operands.push(Arg::new(line, span));
+      start = i + 1;
+    }
+    stmt
+  }
+}
+
+fn is_literal_start(c: char) -> bool {
+  c.is_ascii_digit() || c == '\''
+}
+
+/// the value of an operand, before constants are substituted
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Operand<'a> {
+  Literal(u16),
+  Register(Register),
+  Label(LabelExpr<'a>),
+}
+
+/// which part of an address an operand refers to
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Part {
+  Addr,
+  Hi,
+  Lo,
+}
+
+/// a label with an optional addend, `label + n` or `label - n`, which may be wrapped in `hi(..)` or `lo(..)`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LabelExpr<'a> {
+  pub label: &'a str,
+  /// byte offset of `label` within the operand
+  pub offset: usize,
+  pub part: Part,
+  pub addend: i16,
+}
+
+impl<'a> LabelExpr<'a> {
+  fn parse(s: &'a str) -> Result<Self, String> {
+    let (part, inner) = match s.split_once('(') {
+      Some((f, rest)) => {
+        let part = match f.trim().to_lowercase().as_str() {
+          "hi" => Part::Hi,
+          "lo" => Part::Lo,
+          _ => return err!("unknown function '{}', expected 'hi' or 'lo'", f.trim()),
+        };
+        match rest.strip_suffix(')') {
+          Some(inner) => (part, inner.trim()),
+          None => return err!("expected ')' at the end of '{}'", s),
+        }
+      }
+      None => (Part::Addr, s),
+    };
+    let (label, addend) = match inner.find(['+', '-']) {
+      Some(i) => {
+        let n = inner[i + 1..].trim();
+        let x = match n.starts_with(is_literal_start) {
+          true => Operand::parse_int(n)? as i32,
+          false => return err!("expected a literal after '{}', found '{}'", &inner[i..i + 1], n),
+        };
+        let x = if inner[i..].starts_with('-') { -x } else { x };
+        assert!(
+          (i16::MIN as i32..=i16::MAX as i32).contains(&x),
+          "addend {} out of range ({}..{})",
+          x,
+          i16::MIN,
+          i16::MAX
+        )?;
+        (inner[..i].trim(), x as i16)
+      }
+      None => (inner, 0),
+    };
+    assert!(
+      !label.is_empty() && !label.contains(char::is_whitespace),
+      "invalid label '{}'",
+      label
+    )?;
+    Ok(Self {
+      label,
+      // the label is a slice of the operand
+      offset: label.as_ptr() as usize - s.as_ptr() as usize,
+      part,
+      addend,
+    })
+  }
+
+  /// just a label, such as a name being defined
+  pub fn name(&self) -> Option<&'a str> {
+    (self.part == Part::Addr && self.addend == 0).then_some(self.label)
+  }
+}
+
+impl<'a> Operand<'a> {
+  pub fn parse(s: &'a str) -> Result<Self, String> {
+    match s.chars().next() {
+      Some('-') if !s[1..].starts_with(is_literal_start) => err!("cannot negate '{}'", &s[1..]),
+      Some('-') => match Self::parse_int(&s[1..])? {
+        n if n <= 0x8000 => Ok(Self::Literal(negate(n as u16))),
+        _ => err!("literal '{}' out of range ({}..{})", s, i16::MIN, u16::MAX),
+      },
+      Some(c) if c.is_ascii_digit() && is_label_ref(s.split(['+', '-']).next().unwrap_or_default().trim_end()) => {
+        Ok(Self::Label(LabelExpr::parse(s)?))
+      }
+      Some(c) if is_literal_start(c) => match Self::parse_int(s)? {
+        n if n <= u16::MAX as u32 => Ok(Self::Literal(n as u16)),
+        _ => err!("literal '{}' out of range ({}..{})", s, i16::MIN, u16::MAX),
+      },
+      Some('%') => match Register::from_str(&s[1..].to_lowercase()) {
+        Ok(r) => Ok(Self::Register(r)),
+        Err(_) => err!("unknown register '{}'", s),
+      },
+      Some('"') => err!("unexpected string literal {}", s),
+      Some(_) => Ok(Self::Label(LabelExpr::parse(s)?)),
+      None => err!("empty operand"),
+    }
+  }
+
+  /// parses an unsigned numeric or character literal, range checking is left to the caller
+  fn parse_int(s: &str) -> Result<u32, String> {
+    let mut chars = s.chars();
+    match chars.next() {
+      Some('0') => match chars.next() {
+        Some('x') => Self::parse_radix(s, 16),
+        Some('X') => Self::parse_radix(s, 16),
+        Some('o') => Self::parse_radix(s, 8),
+        Some('O') => Self::parse_radix(s, 8),
+        Some('b') => Self::parse_radix(s, 2),
+        Some('B') => Self::parse_radix(s, 2),
+        Some(c) if c.is_ascii_digit() => Self::parse_radix(s, 10),
+        Some(c) => err!("unknown base '{}'", c),
+        None => Ok(0),
+      },
+      Some('\'') => Self::parse_char(s),
+      _ => Self::parse_radix(s, 10),
+    }
+  }
+
+  /// accounts for 0x prefix when radix != 10
+  fn parse_radix(s: &str, radix: u32) -> Result<u32, String> {
+    match u32::from_str_radix(if radix == 10 { s } else { &s[2..] }, radix) {
+      Ok(n) => Ok(n),
+      Err(_) => err!("could not parse literal '{}'", s),
+    }
+  }
+
+  /// accepts a single ascii character or escape sequence in single quotes
+  fn parse_char(s: &str) -> Result<u32, String> {
+    let c = match s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+      Some("\\n") => '\n',
+      Some("\\t") => '\t',
+      Some("\\0") => '\0',
+      Some("\\\\") => '\\',
+      Some("\\'") => '\'',
+      Some(c) if c.len() == 1 => c.chars().next().unwrap(),
+      _ => return err!("could not parse character literal {}", s),
+    };
+    Ok(c as u32)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_lex() {
+    let kinds: Vec<_> = lex("loop: add %r1, -5, ';' \"a, b\" 1b 0x1f ; done")
+      .into_iter()
+      .map(|t| t.kind)
+      .collect();
+    assert_eq!(
+      kinds,
+      [
+        TokenKind::Ident,
+        TokenKind::Colon,
+        TokenKind::Ident,
+        TokenKind::Register,
+        TokenKind::Comma,
+        TokenKind::Number,
+        TokenKind::Comma,
+        TokenKind::Char,
+        TokenKind::String,
+        TokenKind::Ident,
+        TokenKind::Number,
+        TokenKind::Comment,
+      ]
+    );
+    assert_eq!(lex("'\\'").last().unwrap().kind, TokenKind::Error);
+  }
+
+  #[test]
+  fn test_parse_statement() {
+    let stmt = Statement::parse("  mov %r1, ',' ;; comment");
+    assert_eq!(stmt.code.span, 2..14);
+    assert_eq!(stmt.mnemonic.unwrap().text, "mov");
+    let operands: Vec<_> = stmt.operands.iter().map(|op| (op.text, op.span.clone())).collect();
+    assert_eq!(operands, [("%r1", 6..9), ("','", 11..14)]);
+    let values: Vec<_> = stmt.operands.iter().map(|op| op.value.clone()).collect();
+    assert_eq!(values, [Ok(Operand::Register(Register::R1)), Ok(Operand::Literal(b',' as u16))]);
+    assert_eq!(stmt.comment.unwrap().text, "; comment");
+
+    let stmt = Statement::parse(".loop:");
+    assert_eq!(stmt.label.unwrap().span, 0..5);
+    let stmt = Statement::parse("add %r1,, 2,");
+    let operands: Vec<_> = stmt.operands.iter().map(|op| op.text).collect();
+    assert_eq!(operands, ["%r1", "", "2"]);
+    assert_eq!(stmt.operands[1].value, err!("empty operand"));
+  }
+
+  #[test]
+  fn test_parse_label_expr() {
+    let expr = |label, offset, part, addend| {
+      Operand::Label(LabelExpr {
+        label,
+        offset,
+        part,
+        addend,
+      })
+    };
+    assert_eq!(Operand::parse("table + 3"), Ok(expr("table", 0, Part::Addr, 3)));
+    assert_eq!(Operand::parse("table-0x10"), Ok(expr("table", 0, Part::Addr, -16)));
+    assert_eq!(Operand::parse("hi(table)"), Ok(expr("table", 3, Part::Hi, 0)));
+    assert_eq!(Operand::parse("LO( .end + 1 )"), Ok(expr(".end", 4, Part::Lo, 1)));
+    assert_eq!(Operand::parse("1b + 4"), Ok(expr("1b", 0, Part::Addr, 4)));
+    assert_eq!(Operand::parse("'+'"), Ok(Operand::Literal(b'+' as u16)));
+    std::assert!(Operand::parse("table + x").is_err());
+    std::assert!(Operand::parse("table + 0x8000").is_err());
+    std::assert!(Operand::parse("mid(table)").is_err());
+    std::assert!(Operand::parse("hi(table").is_err());
+  }
+}
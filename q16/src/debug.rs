@@ -0,0 +1,151 @@
+use std::ops::Range;
+use crate::{err, assert};
+use crate::obj::{self, Obj, Reader, TableValue};
+
+pub const MAGIC: &[u8] = b"q16d";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 6;
+
+/// source lines and labels by address, written by the linker alongside a binary so the emulator can show where it is
+#[derive(Default, Debug)]
+pub struct DebugInfo {
+  files: Vec<String>,
+  /// the addresses each line assembled to, the file it is from and the zero based line, in order of address
+  lines: Vec<(Range<u16>, u16, u32)>,
+  /// in order of address
+  symbols: Vec<(u16, String)>,
+}
+
+impl DebugInfo {
+  /// relocates the line table and labels of a linked object, given the address of each section from `Obj::layout`
+  pub fn new(obj: &Obj, addrs: &[u16]) -> Self {
+    let mut debug = Self::default();
+    for (l, file) in obj.lines() {
+      let file = match debug.files.iter().position(|f| f == file) {
+        Some(i) => i,
+        None => {
+          debug.files.push(file.to_string());
+          debug.files.len() - 1
+        }
+      };
+      let start = addrs[l.loc.section].wrapping_add(l.loc.offset);
+      debug.lines.push((start..start.wrapping_add(l.len), file as u16, l.line));
+    }
+    debug.lines.sort_by_key(|(r, _, _)| r.start);
+    // local labels are named as they were in source, without the suffix `Obj::extend` gives them
+    for (label, loc) in obj.labels() {
+      debug
+        .symbols
+        .push((addrs[loc.section].wrapping_add(loc.offset), obj::source_name(label).to_string()));
+    }
+    debug.symbols.sort();
+    debug
+  }
+
+  /// the source files the lines are from, as they were named to the assembler
+  pub fn files(&self) -> &[String] {
+    &self.files
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.lines.is_empty() && self.symbols.is_empty()
+  }
+
+  /// the file and zero based line which the code at an address was assembled from
+  pub fn line_at(&self, addr: u16) -> Option<(&str, u32)> {
+    let i = self.lines.partition_point(|(r, _, _)| r.start <= addr).checked_sub(1)?;
+    let (range, file, line) = &self.lines[i];
+    range.contains(&addr).then(|| (self.files[*file as usize].as_str(), *line))
+  }
+
+  /// the nearest label at or before an address, and how far past it the address is
+  pub fn symbol_at(&self, addr: u16) -> Option<(&str, u16)> {
+    let i = self.symbols.partition_point(|(a, _)| *a <= addr).checked_sub(1)?;
+    let (a, name) = &self.symbols[i];
+    Some((name.as_str(), addr - a))
+  }
+
+  pub fn load(data: &[u8]) -> Result<Self, String> {
+    assert!(data.starts_with(MAGIC), "invalid magic bytes")?;
+    let mut header = Reader::new(&data[MAGIC.len()..]);
+    let version = header.u16()?;
+    let checksum = header.u32()?;
+    assert!(
+      version <= VERSION,
+      "unsupported debug info version {}, expected at most {}",
+      version,
+      VERSION
+    )?;
+    assert!(
+      obj::crc32(&data[HEADER_LEN..]) == checksum,
+      "checksum mismatch, the debug info is corrupt"
+    )?;
+
+    let mut r = Reader::new(&data[HEADER_LEN..]);
+    let mut debug = Self {
+      files: obj::parse_table::<()>(&mut r)?.into_iter().map(|(f, _)| f).collect(),
+      ..Self::default()
+    };
+    for _ in 0..r.u32()? {
+      let start = r.u16()?;
+      let end = r.u16()?;
+      let file = r.u16()?;
+      assert!((file as usize) < debug.files.len(), "invalid file index {}", file)?;
+      debug.lines.push((start..end, file, r.u32()?));
+    }
+    debug.symbols = obj::parse_table::<u16>(&mut r)?.into_iter().map(|(s, a)| (a, s)).collect();
+    assert!(r.is_empty(), "trailing bytes after the symbol table")?;
+    assert!(
+      debug.lines.is_sorted_by_key(|(r, _, _)| r.start) && debug.symbols.is_sorted(),
+      "debug info is out of order"
+    )?;
+    Ok(debug)
+  }
+
+  pub fn out(&self) -> Vec<u8> {
+    let mut body = vec![];
+    obj::out_table(&mut body, self.files.iter().map(|f| (f.clone(), ())));
+    body.extend((self.lines.len() as u32).to_le_bytes());
+    for (range, file, line) in &self.lines {
+      range.start.out(&mut body);
+      range.end.out(&mut body);
+      file.out(&mut body);
+      body.extend(line.to_le_bytes());
+    }
+    obj::out_table(&mut body, self.symbols.iter().map(|(a, s)| (s.clone(), *a)));
+
+    let mut out = Vec::from(MAGIC);
+    out.extend(VERSION.to_le_bytes());
+    out.extend(obj::crc32(&body).to_le_bytes());
+    out.extend(body);
+    out
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::asm;
+  use crate::link::LinkConfig;
+
+  #[test]
+  fn test_debug_info() {
+    let assemble = |file, src| Obj::load(&asm::assemble_file(file, src).out_obj()).unwrap();
+    let mut obj = assemble("a.asm", ".extern main\n  jmp main\n.data\n.db 1\n");
+    obj
+      .extend(assemble("b.asm", ".local helper\nmain:\n  nop\n\nhelper:\n  jmp helper\n"))
+      .unwrap();
+    let addrs = obj.layout(&LinkConfig::parse("base 0x100").unwrap()).unwrap();
+    let debug = DebugInfo::load(&DebugInfo::new(&obj, &addrs).out()).unwrap();
+
+    assert_eq!(debug.line_at(0x100), Some(("a.asm", 1)));
+    assert_eq!(debug.line_at(0x107), Some(("b.asm", 2)));
+    assert_eq!(debug.line_at(0x108), Some(("b.asm", 5)));
+    assert_eq!(debug.line_at(0x10c), Some(("a.asm", 3)));
+    assert_eq!(debug.line_at(0x10d), None);
+    assert_eq!(debug.line_at(0xff), None);
+    assert_eq!(debug.symbol_at(0x10b), Some(("helper", 3)));
+    assert_eq!(debug.symbol_at(0x104), Some(("main", 0)));
+    assert_eq!(debug.symbol_at(0x100), None);
+  }
+}
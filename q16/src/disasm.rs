@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::{Opcode, Register, Instruction, sts};
+use crate::obj::{Obj, SectionKind};
+
+/// runs of zeroes at least this long are shown as `.skip`
+const MIN_SKIP: usize = 8;
+
+/// a decoded instruction or piece of data
+pub struct Line<'a> {
+  pub addr: u16,
+  pub bytes: &'a [u8],
+  /// labels defined at this address
+  pub labels: Vec<&'a str>,
+  /// empty for labels after the end of the data
+  pub text: String,
+}
+
+enum Chunk<'a> {
+  Instr(Instruction),
+  /// a label reference outside of an instruction, and its size
+  Reloc(&'a str, usize),
+  Zeroes(usize),
+  /// undecodable
+  Byte(u8),
+}
+
+pub struct Disassembler<'a> {
+  data: &'a [u8],
+  /// address of the first byte of data
+  base: u16,
+  labels: BTreeMap<u16, Vec<&'a str>>,
+  /// the label expression referenced by the bytes at each address, and how many bytes
+  relocs: HashMap<u16, (String, usize)>,
+  /// runs of zeroes at least this long are shown as `.skip`
+  min_skip: usize,
+}
+
+impl<'a> Disassembler<'a> {
+  pub fn new(data: &'a [u8], base: u16) -> Self {
+    Self {
+      data,
+      base,
+      labels: BTreeMap::new(),
+      relocs: HashMap::new(),
+      min_skip: MIN_SKIP,
+    }
+  }
+
+  /// disassembles one section of an object, using its symbol table and relocations to name labels
+  pub fn from_section(obj: &'a Obj, section: usize) -> Self {
+    let mut dis = Self::new(&obj.sections[section].data, 0);
+    // zero filled sections can only be written with `.skip`
+    if obj.sections[section].kind == SectionKind::Zero {
+      dis.min_skip = 1;
+    }
+    for (label, loc) in obj.labels().filter(|(_, loc)| loc.section == section) {
+      dis.labels.entry(loc.offset).or_default().push(label);
+    }
+    for labels in dis.labels.values_mut() {
+      labels.sort();
+    }
+    for (label, r) in obj.label_uses().filter(|(_, r)| r.loc.section == section) {
+      dis.relocs.insert(r.loc.offset, (r.expr(label), r.kind.size()));
+    }
+    dis
+  }
+
+  pub fn lines(&self) -> Vec<Line<'a>> {
+    let mut out = vec![];
+    let mut pos = 0;
+    while pos < self.data.len() {
+      let addr = self.base.wrapping_add(pos as u16);
+      let (len, text) = match self.decode(pos) {
+        Chunk::Instr(instr) => (
+          4,
+          self.format_instr(instr, self.relocs.get(&addr.wrapping_add(2)).map(|(e, _)| e.as_str())),
+        ),
+        Chunk::Reloc(expr, 1) => (1, format!(".db {}", expr)),
+        Chunk::Reloc(expr, size) => (size, format!(".dw {}", expr)),
+        Chunk::Zeroes(n) => (n, format!(".skip {}", n)),
+        Chunk::Byte(x) => (1, format!(".db 0x{:x}", x)),
+      };
+      out.push(Line {
+        addr,
+        bytes: &self.data[pos..pos + len],
+        labels: self.labels.get(&addr).cloned().unwrap_or_default(),
+        text,
+      });
+      pos += len;
+    }
+    // labels at the very end, such as the top of a stack, are given a line with no text
+    let end = self.base.wrapping_add(pos as u16);
+    if let Some(labels) = self.labels.get(&end) {
+      out.push(Line {
+        addr: end,
+        bytes: &[],
+        labels: labels.clone(),
+        text: String::new(),
+      });
+    }
+    out
+  }
+
+  /// decodes whatever is at `pos`, without crossing a label
+  fn decode(&self, pos: usize) -> Chunk<'_> {
+    let addr = self.base.wrapping_add(pos as u16);
+    let end = match self.labels.range(addr.saturating_add(1)..).next() {
+      Some((a, _)) => (a.wrapping_sub(self.base) as usize).min(self.data.len()),
+      None => self.data.len(),
+    };
+    let available = &self.data[pos..end];
+
+    if let Some((expr, size)) = self.relocs.get(&addr).filter(|(_, size)| available.len() >= *size) {
+      return Chunk::Reloc(expr, *size);
+    }
+    let zeroes = available.iter().take_while(|b| **b == 0).count();
+    if zeroes >= self.min_skip {
+      return Chunk::Zeroes(zeroes);
+    }
+    if let Some(instr) = available
+      .first_chunk()
+      .and_then(|bytes| Instruction::from_u32(u32::from_le_bytes(*bytes)))
+    {
+      return Chunk::Instr(instr);
+    }
+    Chunk::Byte(available[0])
+  }
+
+  /// `label` replaces the immediate if it was a label reference
+  fn format_instr(&self, instr: Instruction, label: Option<&str>) -> String {
+    match pseudo(instr, label) {
+      Some(s) => s,
+      None => match (instr, label) {
+        (Instruction::I(opc, rd, r1, _), Some(l)) => format!("{} %{}, %{}, {}", opc, rd, r1, l),
+        _ => instr.to_string(),
+      },
+    }
+  }
+}
+
+/// recognises instructions produced by the assembler's pseudo-instructions
+pub fn pseudo(instr: Instruction, label: Option<&str>) -> Option<String> {
+  let imm = |x: u16| label.map(|l| l.to_string()).unwrap_or_else(|| format!("0x{:x}", x));
+  Some(match instr {
+    Instruction::R(Opcode::Add, Register::R0, Register::R0, Register::R0) => "nop".to_string(),
+    Instruction::I(Opcode::And, Register::STS, Register::STS, x) if x == !(1 << sts::RUN) => "hlt".to_string(),
+    Instruction::I(Opcode::Add, Register::PC, Register::R0, x) => format!("jmp {}", imm(x)),
+    Instruction::I(Opcode::Add, Register::PC, r1, 0) if label.is_none() => format!("jmp %{}", r1),
+    Instruction::I(Opcode::Add, Register::PC, Register::PC, _) if label.is_some() => format!("br {}", imm(0)),
+    Instruction::I(opc, Register::R0, Register::PC, _) if label.is_some() && is_jump(opc) => {
+      format!("{} {}", opc.to_string().replacen('j', "b", 1), imm(0))
+    }
+    Instruction::I(opc, Register::R0, Register::R0, x) if is_jump(opc) => format!("{} {}", opc, imm(x)),
+    Instruction::I(opc, Register::R0, r1, 0) if is_jump(opc) && label.is_none() => format!("{} %{}", opc, r1),
+    Instruction::I(opc, Register::R0, r1, x) if is_jump(opc) => format!("{} %{}, {}", opc, r1, imm(x)),
+    Instruction::I(Opcode::Add, Register::R0, r1, x) if label.is_none() => format!("cmp %{}, 0x{:x}", r1, x.wrapping_neg()),
+    Instruction::R(Opcode::Sub, Register::R0, r1, r2) => format!("cmp %{}, %{}", r1, r2),
+    Instruction::I(Opcode::Add, rd, Register::R0, x) => format!("mov %{}, {}", rd, imm(x)),
+    Instruction::I(Opcode::Add, rd, r1, 0) if label.is_none() => format!("mov %{}, %{}", rd, r1),
+    _ => return None,
+  })
+}
+
+fn is_jump(opc: Opcode) -> bool {
+  matches!(
+    opc,
+    Opcode::Jeq | Opcode::Jne | Opcode::Jgt | Opcode::Jlt | Opcode::Jge | Opcode::Jle
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::asm::Assembler;
+
+  #[test]
+  fn test_disassemble_obj() {
+    let mut assembler = Assembler::new();
+    let src = "start:\n  nop\n  mov %r1, 5\n  cmp %r1, %r2\n  beq start\n  lw %r2, %r1, data\n  hlt\ndata:\n  .dw start + 2\n  .db 0xff\n  .db hi(data)\n";
+    assembler.assemble(src).unwrap();
+    let dis = Disassembler::from_section(&assembler.obj, 0);
+    let lines: Vec<_> = dis.lines().into_iter().map(|l| (l.addr, l.labels, l.text)).collect();
+    assert_eq!(
+      lines,
+      [
+        (0x0, vec!["start"], "nop".to_string()),
+        (0x4, vec![], "mov %r1, 0x5".to_string()),
+        (0x8, vec![], "cmp %r1, %r2".to_string()),
+        (0xc, vec![], "beq start".to_string()),
+        (0x10, vec![], "lw %r2, %r1, data".to_string()),
+        (0x14, vec![], "hlt".to_string()),
+        (0x18, vec!["data"], ".dw start + 2".to_string()),
+        (0x1a, vec![], ".db 0xff".to_string()),
+        (0x1b, vec![], ".db hi(data)".to_string()),
+      ]
+    );
+    // addresses wrap at the end of memory
+    let bytes = Instruction::R(Opcode::Add, Register::R0, Register::R0, Register::R0)
+      .as_u32()
+      .to_le_bytes();
+    let lines = Disassembler::new(&bytes, 0xfffe).lines();
+    assert_eq!((lines[0].addr, lines[0].text.as_str()), (0xfffe, "nop"));
+  }
+}
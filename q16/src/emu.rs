@@ -8,6 +8,12 @@ pub struct Emulator {
   pub registers: Registers,
 }
 
+impl Default for Emulator {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl Emulator {
   pub fn new() -> Self {
     Self {
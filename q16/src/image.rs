@@ -0,0 +1,268 @@
+use std::fmt::Write;
+use strum::{Display, EnumString};
+use crate::{err, assert};
+
+/// bytes of data in each record of the text formats
+const RECORD_LEN: usize = 16;
+
+/// a run of bytes and the address it is loaded at
+pub type Segment = (u16, Vec<u8>);
+
+/// how a linked image is written out
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Format {
+  /// the raw bytes from address 0 to the highest, zero filled, as they are loaded
+  #[default]
+  Bin,
+  /// intel hex, whose records each carry their address
+  Ihex,
+  /// motorola s-records, whose records each carry their address
+  Srec,
+}
+
+impl Format {
+  /// text formats begin with the character every record does, anything else, or anything that isn't ascii, is raw
+  pub fn detect(data: &[u8]) -> Self {
+    match data.first() {
+      Some(b':') if data.is_ascii() => Self::Ihex,
+      Some(b'S') if data.is_ascii() => Self::Srec,
+      _ => Self::Bin,
+    }
+  }
+}
+
+pub fn encode(segments: &[Segment], format: Format) -> Vec<u8> {
+  match format {
+    Format::Bin => {
+      let mut bin = vec![];
+      for (addr, data) in segments {
+        let range = *addr as usize..*addr as usize + data.len();
+        if bin.len() < range.end {
+          bin.resize(range.end, 0);
+        }
+        bin[range].copy_from_slice(data);
+      }
+      bin
+    }
+    Format::Ihex => {
+      let mut out = String::new();
+      for (addr, chunk) in records(segments) {
+        ihex_record(&mut out, 0x00, addr, chunk);
+      }
+      ihex_record(&mut out, 0x01, 0, &[]);
+      out.into_bytes()
+    }
+    Format::Srec => {
+      let mut out = String::new();
+      srec_record(&mut out, 0, 0, &[]);
+      for (addr, chunk) in records(segments) {
+        srec_record(&mut out, 1, addr, chunk);
+      }
+      srec_record(&mut out, 9, 0, &[]);
+      out.into_bytes()
+    }
+  }
+}
+
+/// the segments of an image in any format, raw binaries are loaded at address 0
+pub fn decode(data: &[u8]) -> Result<Vec<Segment>, String> {
+  let format = Format::detect(data);
+  if format == Format::Bin {
+    assert!(data.len() <= 1 << 16, "the binary is larger than memory")?;
+    return Ok(vec![(0, data.to_vec())]);
+  }
+  // only ascii is detected as text
+  let text = std::str::from_utf8(data).unwrap();
+  let mut segments = vec![];
+  // upper bits of the address from intel hex extended address records
+  let mut upper = 0;
+  for (n, line) in text.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() {
+      continue;
+    }
+    let result = match format {
+      Format::Ihex => decode_ihex(line, &mut upper),
+      _ => decode_srec(line),
+    };
+    match result {
+      Ok(Some(Record::Data(addr, data))) => {
+        assert!(
+          addr as usize + data.len() <= 1 << 16,
+          "line {}: data at 0x{:x} is outside of memory",
+          n + 1,
+          addr
+        )?;
+        segments.push((addr as u16, data));
+      }
+      Ok(Some(Record::End)) => return Ok(segments),
+      Ok(None) => {}
+      Err(e) => return err!("line {}: {}", n + 1, e),
+    }
+  }
+  err!("missing end of file record")
+}
+
+enum Record {
+  Data(u32, Vec<u8>),
+  End,
+}
+
+/// splits segments into chunks which each fit in a record
+fn records(segments: &[Segment]) -> impl Iterator<Item = (u16, &[u8])> {
+  segments.iter().flat_map(|(addr, data)| {
+    data
+      .chunks(RECORD_LEN)
+      .enumerate()
+      .map(move |(i, chunk)| (addr.wrapping_add((i * RECORD_LEN) as u16), chunk))
+  })
+}
+
+/// the checksum is the two's complement of the sum of every other byte
+fn ihex_record(out: &mut String, kind: u8, addr: u16, data: &[u8]) {
+  let mut bytes = vec![data.len() as u8];
+  bytes.extend(addr.to_be_bytes());
+  bytes.push(kind);
+  bytes.extend(data);
+  let sum = bytes.iter().fold(0u8, |a, b| a.wrapping_add(*b));
+  bytes.push(sum.wrapping_neg());
+  out.push(':');
+  out.push_str(&hex(&bytes));
+  out.push('\n');
+}
+
+/// the checksum is the one's complement of the sum of the count, address and data
+fn srec_record(out: &mut String, kind: u8, addr: u16, data: &[u8]) {
+  let mut bytes = vec![data.len() as u8 + 3];
+  bytes.extend(addr.to_be_bytes());
+  bytes.extend(data);
+  let sum = bytes.iter().fold(0u8, |a, b| a.wrapping_add(*b));
+  bytes.push(!sum);
+  write!(out, "S{}{}", kind, hex(&bytes)).unwrap();
+  out.push('\n');
+}
+
+fn decode_ihex(line: &str, upper: &mut u32) -> Result<Option<Record>, String> {
+  let Some(bytes) = line.strip_prefix(':') else {
+    return err!("expected a record beginning with ':'");
+  };
+  let bytes = unhex(bytes)?;
+  assert!(
+    bytes.len() >= 5 && bytes.len() == bytes[0] as usize + 5,
+    "the record's length is wrong"
+  )?;
+  assert!(bytes.iter().fold(0u8, |a, b| a.wrapping_add(*b)) == 0, "checksum mismatch")?;
+  let addr = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+  let data = &bytes[4..bytes.len() - 1];
+  let word = || {
+    data
+      .first_chunk()
+      .map(|w| u16::from_be_bytes(*w) as u32)
+      .ok_or("the record is too short")
+  };
+  Ok(match bytes[3] {
+    0x00 => Some(Record::Data(*upper + addr, data.to_vec())),
+    0x01 => Some(Record::End),
+    // extended segment and linear addresses
+    0x02 => {
+      *upper = word()? << 4;
+      None
+    }
+    0x04 => {
+      *upper = word()? << 16;
+      None
+    }
+    // start addresses, execution always starts at 0
+    0x03 | 0x05 => None,
+    kind => return err!("unknown record type {:02x}", kind),
+  })
+}
+
+fn decode_srec(line: &str) -> Result<Option<Record>, String> {
+  let Some(rest) = line.strip_prefix('S') else {
+    return err!("expected a record beginning with 'S'");
+  };
+  let Some(kind) = rest.chars().next().and_then(|c| c.to_digit(10)) else {
+    return err!("expected a record type after 'S'");
+  };
+  let bytes = unhex(&rest[1..])?;
+  assert!(
+    !bytes.is_empty() && bytes.len() == bytes[0] as usize + 1,
+    "the record's length is wrong"
+  )?;
+  assert!(bytes.iter().fold(0u8, |a, b| a.wrapping_add(*b)) == 0xff, "checksum mismatch")?;
+  let addr_len = match kind {
+    1 | 9 => 2,
+    2 | 8 => 3,
+    3 | 7 => 4,
+    _ => 0,
+  };
+  assert!(bytes.len() >= addr_len + 2, "the record is too short")?;
+  let addr = bytes[1..1 + addr_len].iter().fold(0, |a, b| a << 8 | *b as u32);
+  let data = &bytes[1 + addr_len..bytes.len() - 1];
+  Ok(match kind {
+    1..=3 => Some(Record::Data(addr, data.to_vec())),
+    7..=9 => Some(Record::End),
+    // the header and record counts
+    0 | 5 | 6 => None,
+    _ => return err!("unknown record type S{}", kind),
+  })
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn unhex(s: &str) -> Result<Vec<u8>, String> {
+  assert!(s.len().is_multiple_of(2) && s.is_ascii(), "invalid hex digits")?;
+  (0..s.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(&s[i..i + 2], 16).or(err!("invalid hex digits '{}'", &s[i..i + 2])))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_formats() {
+    let segments = vec![(0x100, vec![1, 2]), (0x200, (0..20).collect())];
+    assert_eq!(
+      String::from_utf8(encode(&segments, Format::Ihex))
+        .unwrap()
+        .lines()
+        .take(2)
+        .collect::<Vec<_>>(),
+      [":020100000102FA", ":10020000000102030405060708090A0B0C0D0E0F76"]
+    );
+    assert_eq!(
+      String::from_utf8(encode(&segments, Format::Srec))
+        .unwrap()
+        .lines()
+        .take(2)
+        .collect::<Vec<_>>(),
+      ["S0030000FC", "S10501000102F6"]
+    );
+    let mut bin = encode(&segments, Format::Bin);
+    assert_eq!(bin.len(), 0x214);
+    assert_eq!(bin[0x100..0x102], [1, 2]);
+    assert_eq!(decode(&bin).unwrap(), [(0, bin.clone())]);
+
+    for format in [Format::Ihex, Format::Srec] {
+      let text = encode(&segments, format);
+      assert_eq!(Format::detect(&text), format);
+      assert_eq!(
+        decode(&text).unwrap(),
+        [(0x100, vec![1, 2]), (0x200, (0..16).collect()), (0x210, (16..20).collect())]
+      );
+      std::assert!(decode(&text[..text.len() - 12]).is_err());
+    }
+
+    bin = b":020100000102FB\n:00000001FF\n".to_vec();
+    assert_eq!(decode(&bin).err().unwrap(), "line 1: checksum mismatch");
+    bin = b":020000040001F9\n:0100000001FE\n:00000001FF\n".to_vec();
+    assert_eq!(decode(&bin).err().unwrap(), "line 2: data at 0x10000 is outside of memory");
+  }
+}
@@ -1,7 +1,12 @@
 #![feature(map_try_insert)]
 pub mod util;
 pub mod obj;
+pub mod link;
+pub mod archive;
+pub mod debug;
+pub mod image;
 pub mod asm;
+pub mod disasm;
 pub mod emu;
 
 use std::fmt;
@@ -15,7 +20,11 @@ pub mod sts {
 }
 pub mod addr {
   pub const VRAM: u16 = 0xc000;
+  /// 128x96 pixels of one byte each
+  pub const VRAM_LEN: u16 = 0x3000;
   pub const SERIAL_IO: u16 = 0xf000;
+  /// a word holding the number of bytes waiting, then the byte to read or write
+  pub const SERIAL_IO_LEN: u16 = 3;
 }
 
 #[rustfmt::skip]
@@ -126,6 +135,7 @@ impl Instruction {
   }
 }
 
+/// canonical assembly, which always assembles back to the same instruction
 impl fmt::Display for Instruction {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
@@ -134,3 +144,52 @@ impl fmt::Display for Instruction {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use strum::IntoEnumIterator;
+  use crate::asm::Assembler;
+
+  fn assert_round_trip(instr: Instruction) {
+    let mut assembler = Assembler::new();
+    let src = instr.to_string();
+    if let Err(e) = assembler.assemble(&src) {
+      panic!("couldn't assemble '{}': {}", src, e[0].msg);
+    }
+    std::assert_eq!(
+      assembler.obj.sections[0].data,
+      instr.as_u32().to_le_bytes(),
+      "'{}' assembled differently",
+      src
+    );
+  }
+
+  #[test]
+  fn test_display_round_trip() {
+    let imms = [0, 1, 0x7f, 0x80, 0xff, 0x100, 0x7fff, 0x8000, 0xfffe, 0xffff];
+    for opc in (1..0x80).filter_map(Opcode::from_repr) {
+      for (rd, r1) in Register::iter().flat_map(|rd| Register::iter().map(move |r1| (rd, r1))) {
+        if opc.valid_r() {
+          for r2 in Register::iter() {
+            assert_round_trip(Instruction::R(opc, rd, r1, r2));
+          }
+        }
+        if opc.valid_i() {
+          for imm in imms {
+            assert_round_trip(Instruction::I(opc, rd, r1, imm));
+          }
+        }
+      }
+    }
+
+    // arbitrary encodings, including unused bits
+    let mut x: u32 = 0x12345678;
+    for _ in 0..100_000 {
+      x = x.wrapping_mul(1664525).wrapping_add(1013904223);
+      if let Some(instr) = Instruction::from_u32(x) {
+        assert_round_trip(instr);
+      }
+    }
+  }
+}
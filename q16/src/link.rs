@@ -0,0 +1,323 @@
+use std::iter;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::fmt::Write;
+use crate::{addr, err, assert};
+use crate::obj::{self, Binding, Obj, Placement, SectionKind, TEXT};
+
+/// memory mapped regions which the image must not overlap
+const RESERVED: &[(&str, u16, u16)] = &[
+  ("VRAM", addr::VRAM, addr::VRAM_LEN),
+  ("SERIAL_IO", addr::SERIAL_IO, addr::SERIAL_IO_LEN),
+];
+
+/// where the linker places sections, sections without a fixed address are placed in order from the base address
+///
+/// as a script, each line is either `base <addr>` or `section <name> <addr>`, with `;` starting a comment:
+/// ```text
+/// base 0x0
+/// section .data 0x8000 ; after the rom
+/// ```
+#[derive(Clone, Default, Debug)]
+pub struct LinkConfig {
+  pub base: u16,
+  pub sections: Vec<(String, u16)>,
+}
+
+impl LinkConfig {
+  pub fn parse(script: &str) -> Result<Self, String> {
+    let mut config = Self::default();
+    for (n, line) in script.lines().enumerate() {
+      let line = line.split(';').next().unwrap_or_default();
+      let words: Vec<_> = line.split_whitespace().collect();
+      let result = match words[..] {
+        [] => Ok(()),
+        ["base", addr] => parse_addr(addr).map(|a| config.base = a),
+        ["section", name, addr] => parse_addr(addr).and_then(|a| config.place(name, a)),
+        _ => err!("expected 'base <addr>' or 'section <name> <addr>'"),
+      };
+      if let Err(e) = result {
+        return err!("line {}: {}", n + 1, e);
+      }
+    }
+    Ok(config)
+  }
+
+  /// fixes the address of a section, such as from the command line
+  pub fn place(&mut self, name: &str, addr: u16) -> Result<(), String> {
+    assert!(
+      self.address(name).is_none(),
+      "section '{}' is already placed at 0x{:04x}",
+      name,
+      self.address(name).unwrap_or_default()
+    )?;
+    self.sections.push((name.to_string(), addr));
+    Ok(())
+  }
+
+  pub fn address(&self, section: &str) -> Option<u16> {
+    self.sections.iter().find(|(s, _)| s == section).map(|(_, a)| *a)
+  }
+}
+
+/// accepts decimal or `0x` prefixed hex
+pub fn parse_addr(s: &str) -> Result<u16, String> {
+  let parsed = match s.strip_prefix("0x") {
+    Some(hex) => u16::from_str_radix(hex, 16),
+    None => s.parse(),
+  };
+  match parsed {
+    Ok(a) => Ok(a),
+    Err(_) => err!("invalid address '{}'", s),
+  }
+}
+
+/// checks that the nonempty ranges, named by section, dont overlap each other or a memory mapped region
+pub fn check_overlaps(ranges: &[(&str, Range<usize>)]) -> Result<(), String> {
+  let fmt = |r: &Range<usize>| format!("0x{:04x}..0x{:04x}", r.start, r.end);
+  for (i, (a, ra)) in ranges.iter().enumerate().filter(|(_, (_, r))| !r.is_empty()) {
+    if let Some((b, rb)) = ranges[i + 1..].iter().find(|(_, r)| overlaps(ra, r)) {
+      return err!("section '{}' ({}) overlaps section '{}' ({})", a, fmt(ra), b, fmt(rb));
+    }
+    for (name, start, len) in RESERVED {
+      let region = *start as usize..(*start + *len) as usize;
+      assert!(
+        !overlaps(ra, &region),
+        "section '{}' ({}) overlaps {} ({})",
+        a,
+        fmt(ra),
+        name,
+        fmt(&region)
+      )?;
+    }
+  }
+  Ok(())
+}
+
+/// a summary of where everything ended up, given the address of each section from `Obj::layout`
+/// `inputs` are the name of each linked object and where its sections were placed, as returned by `Obj::extend`
+pub fn map(obj: &Obj, addrs: &[u16], inputs: &[(String, Placement)]) -> String {
+  let mut out = String::new();
+  let abs = |section: usize, offset: usize| addrs[section] as usize + offset;
+  writeln!(out, "start end   size  section").unwrap();
+  let mut sections: Vec<_> = obj.sections.iter().zip(addrs).collect();
+  sections.sort_by_key(|(_, addr)| **addr);
+  for (s, addr) in sections {
+    let start = *addr as usize;
+    writeln!(out, "{:04x}  {:04x}  {:04x}  {}", start, start + s.data.len(), s.data.len(), s.name).unwrap();
+  }
+
+  writeln!(out, "\nstart end   size  section  object").unwrap();
+  let mut parts: Vec<_> = inputs
+    .iter()
+    .flat_map(|(name, parts)| parts.iter().map(move |(i, r)| (abs(*i, r.start)..abs(*i, r.end), *i, name)))
+    .filter(|(r, _, _)| !r.is_empty())
+    .collect();
+  parts.sort_by_key(|(r, _, _)| r.start);
+  for (r, i, name) in parts {
+    writeln!(
+      out,
+      "{:04x}  {:04x}  {:04x}  {:<7}  {}",
+      r.start,
+      r.end,
+      r.len(),
+      obj.sections[i].name,
+      name
+    )
+    .unwrap();
+  }
+
+  // a symbol extends to the next one after it, not counting the local labels scoped to it
+  writeln!(out, "\naddr  size  binding  symbol").unwrap();
+  let mut labels: Vec<_> = obj
+    .labels()
+    .map(|(l, loc)| (abs(loc.section, loc.offset as usize), loc.section, l))
+    .collect();
+  labels.sort();
+  for &(addr, section, label) in &labels {
+    let scoped = format!("{}.", label);
+    let end = labels
+      .iter()
+      .find(|(a, s, l)| *s == section && *a > addr && !l.starts_with(&scoped))
+      .map(|(a, _, _)| *a)
+      .unwrap_or(abs(section, obj.sections[section].data.len()));
+    let binding = obj.binding(label).unwrap_or(Binding::Global);
+    // local labels are shown as they were named in source, without the suffix `Obj::extend` gives them
+    writeln!(out, "{:04x}  {:04x}  {:<7}  {}", addr, end - addr, binding, obj::source_name(label)).unwrap();
+  }
+
+  writeln!(out, "\nstart end   size  free memory before VRAM").unwrap();
+  let mut used: Vec<_> = obj
+    .sections
+    .iter()
+    .zip(addrs)
+    .map(|(s, a)| *a as usize..*a as usize + s.data.len())
+    .collect();
+  used.sort_by_key(|r| r.start);
+  let mut addr = 0;
+  for r in used.into_iter().chain(iter::once(addr::VRAM as usize..addr::VRAM as usize)) {
+    let start = r.start.min(addr::VRAM as usize);
+    if start > addr {
+      writeln!(out, "{:04x}  {:04x}  {:04x}", addr, start, start - addr).unwrap();
+    }
+    addr = addr.max(r.end);
+  }
+  out
+}
+
+/// drops each section of each object which can't be reached by following relocations from the entry label, from
+/// the `.text` of the first object where execution begins, or from a section the config places at a fixed address,
+/// such as a vector table, returning the number of data bytes and zero filled bytes dropped
+/// sections are kept or dropped whole, as code may fall through from one label to the next
+pub fn gc(objs: &mut [Obj], entry: &str, config: &LinkConfig) -> Result<(usize, usize), String> {
+  // where a label used by an object is defined, preferring its own labels unless they are weak, then global ones over
+  // weak ones as `Obj::extend` does
+  let find = |objs: &[Obj], o: usize, label: &str| {
+    let defined = |o: usize| objs[o].labels().find(|(l, _)| *l == label).map(|(_, loc)| (o, loc.section));
+    let with = |binding| (0..objs.len()).find(|i| objs[*i].binding(label) == Some(binding));
+    match objs[o].binding(label) {
+      Some(Binding::Local | Binding::Global) => defined(o),
+      _ => with(Binding::Global).or_else(|| with(Binding::Weak)).and_then(defined),
+    }
+  };
+  let root = (0..objs.len())
+    .find(|i| objs[*i].binding(entry).is_some_and(Binding::is_visible))
+    .and_then(|o| find(objs, o, entry));
+  let Some(root) = root else {
+    return err!("entry label '{}' is not defined", entry);
+  };
+  let mut live = HashSet::from([root]);
+  if let Some(text) = objs.first().and_then(|o| o.sections.iter().position(|s| s.name == TEXT)) {
+    live.insert((0, text));
+  }
+  for (o, obj) in objs.iter().enumerate() {
+    let fixed = obj
+      .sections
+      .iter()
+      .enumerate()
+      .filter(|(_, s)| config.sections.iter().any(|(n, _)| *n == s.name));
+    live.extend(fixed.map(|(i, _)| (o, i)));
+  }
+
+  let mut stack: Vec<_> = live.iter().copied().collect();
+  while let Some((o, section)) = stack.pop() {
+    for (label, _) in objs[o].label_uses().filter(|(_, r)| r.loc.section == section) {
+      if let Some(def) = find(objs, o, label) {
+        if live.insert(def) {
+          stack.push(def);
+        }
+      }
+    }
+  }
+
+  let (mut data, mut zero) = (0, 0);
+  for (o, obj) in objs.iter_mut().enumerate() {
+    for section in 0..obj.sections.len() {
+      if !live.contains(&(o, section)) {
+        let s = &obj.sections[section];
+        match s.kind {
+          SectionKind::Data => data += s.data.len(),
+          SectionKind::Zero => zero += s.data.len(),
+        }
+        obj.clear_section(section);
+      }
+    }
+  }
+  Ok((data, zero))
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+  !a.is_empty() && !b.is_empty() && a.start < b.end && b.start < a.end
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::asm::assemble;
+
+  #[test]
+  fn test_parse_script() {
+    let config = LinkConfig::parse("; rom first\nbase 0x100\n\nsection .data 32768 ; ram\n").unwrap();
+    assert_eq!(config.base, 0x100);
+    assert_eq!(config.address(".data"), Some(0x8000));
+    assert_eq!(
+      LinkConfig::parse("base\n").err().unwrap(),
+      "line 1: expected 'base <addr>' or 'section <name> <addr>'"
+    );
+    assert_eq!(
+      LinkConfig::parse("base 0x10000").err().unwrap(),
+      "line 1: invalid address '0x10000'"
+    );
+    std::assert!(LinkConfig::parse("section .a 0\nsection .a 2").is_err());
+  }
+
+  #[test]
+  fn test_overlaps() {
+    std::assert!(check_overlaps(&[(".text", 0..0x10), (".data", 0x10..0x20), (".bss", 0x20..0x20)]).is_ok());
+    std::assert!(check_overlaps(&[(".text", 0..0x10), (".data", 0xf..0x20)]).is_err());
+    assert_eq!(
+      check_overlaps(&[(".bss", 0xbff0..0xc010)]).err().unwrap(),
+      "section '.bss' (0xbff0..0xc010) overlaps VRAM (0xc000..0xf000)"
+    );
+    std::assert!(check_overlaps(&[(".data", 0xf002..0xf004)]).is_err());
+  }
+
+  #[test]
+  fn test_map() {
+    let mut obj = Obj::new();
+    let mut inputs = vec![];
+    let a = assemble(".extern f\nstart:\n  jmp f\n.bss\nbuf:\n.skip 6\n");
+    inputs.push(("a.o".to_string(), obj.extend(a).unwrap()));
+    let b = assemble(".local g\nf:\n  jmp .end\n.end:\n  jmp g\ng:\n");
+    inputs.push(("b.o".to_string(), obj.extend(b).unwrap()));
+    let addrs = obj.layout(&LinkConfig::parse("base 0x10").unwrap()).unwrap();
+    let map = map(&obj, &addrs, &inputs);
+    let lines: Vec<_> = map.lines().collect();
+    assert_eq!(lines[1..3], ["0010  001c  000c  .text", "001c  0022  0006  .bss"]);
+    assert_eq!(
+      lines[5..8],
+      [
+        "0010  0014  0004  .text    a.o",
+        "0014  001c  0008  .text    b.o",
+        "001c  0022  0006  .bss     a.o"
+      ]
+    );
+    std::assert!(lines.contains(&"0014  0008  global   f"));
+    std::assert!(lines.contains(&"0018  0004  local    f.end"));
+    std::assert!(lines.contains(&"001c  0000  local    g"));
+    assert_eq!(lines[lines.len() - 2..], ["0000  0010  0010", "0022  c000  bfde"]);
+  }
+
+  #[test]
+  fn test_gc() {
+    let mut objs = vec![
+      assemble(".extern main\n  jmp main\n.bss\n.skip 2\n"),
+      assemble(".extern helper\nmain:\n  jmp helper\n.section .text.dead\nunused:\n  jmp unused\n"),
+      assemble(".extern main\nhelper:\n  jmp main\n.data\n.dw helper\n"),
+    ];
+    let config = LinkConfig::default();
+    // the first object's code is kept, though nothing references it, while its unreferenced bss is dropped
+    assert_eq!(gc(&mut objs, "main", &config).unwrap(), (4 + 2, 2));
+    let sizes: Vec<Vec<_>> = objs.iter().map(|o| o.sections.iter().map(|s| s.data.len()).collect()).collect();
+    assert_eq!(sizes, [vec![4, 0], vec![4, 0], vec![4, 0]]);
+    std::assert!(objs[1].has_label("main") && !objs[1].has_label("unused"));
+    std::assert!(gc(&mut objs, "missing", &config).is_err());
+
+    // references to a weak label reach the global definition which replaces it, even from its own object
+    let mut objs = vec![
+      assemble(".weak handler\n  jmp handler\n.section .text.default\nhandler:\n  hlt\n"),
+      assemble(".section .text.handler\nhandler:\n  nop\n"),
+    ];
+    assert_eq!(gc(&mut objs, "handler", &config).unwrap(), (4, 0));
+    std::assert!(!objs[0].has_label("handler") && objs[1].has_label("handler"));
+
+    // sections placed at a fixed address are kept, though nothing references them
+    let mut objs = vec![
+      assemble("start:\n  hlt\n"),
+      assemble(".section .vectors\n.dw 0\n.section .text.dead\n  nop\n"),
+    ];
+    let config = LinkConfig::parse("section .vectors 0x100\n").unwrap();
+    assert_eq!(gc(&mut objs, "start", &config).unwrap(), (4, 0));
+    assert_eq!(objs[1].sections.iter().map(|s| s.data.len()).collect::<Vec<_>>(), [0, 2, 0]);
+  }
+}
@@ -1,108 +1,1120 @@
 use std::collections::hash_map::{HashMap, Entry};
+use strum::{Display, FromRepr};
+use std::ops::Range;
 use crate::{Instruction, err, assert};
+use crate::link::{self, LinkConfig};
+use crate::image::{self, Format, Segment};
 
-/// magic bytes for object files
-const MAGIC: &[u8] = b"q16";
+/// magic bytes for object files, which dont begin with the legacy magic so neither can be mistaken for the other
+const MAGIC: &[u8] = b"\x7fq16";
+/// objects written before the header was added, followed by the labels and label uses, both with u16 offsets, and
+/// then the code
+const LEGACY_MAGIC: &[u8] = b"q16";
+/// bumped whenever the layout after the header changes, objects of any other version are rejected
+const VERSION: u16 = 1;
+/// flags which this version understands, objects with any others set are rejected
+const KNOWN_FLAGS: u16 = 0;
+/// magic, version, flags, section count and checksum
+const HEADER_LEN: usize = MAGIC.len() + 10;
+/// the section code is assembled into unless another is chosen, which is always first
+pub const TEXT: &str = ".text";
 
-pub struct Obj {
+#[derive(Copy, Clone, PartialEq, Eq, Display, Debug, FromRepr)]
+#[strum(serialize_all = "snake_case")]
+#[repr(u8)]
+pub enum RelocKind {
+  /// the absolute address of the label
+  Abs,
+  /// the address of the label relative to the end of the instruction containing it, ie the value of %pc as it executes
+  PcRel,
+  /// the high byte of the address
+  Hi,
+  /// the low byte of the address
+  Lo,
+  /// the absolute address into a byte, which it must fit in
+  Byte,
+  /// the high byte of the address into a byte
+  HiByte,
+  /// the low byte of the address into a byte
+  LoByte,
+}
+
+impl RelocKind {
+  /// the number of bytes replaced
+  pub fn size(self) -> usize {
+    match self {
+      Self::Byte | Self::HiByte | Self::LoByte => 1,
+      _ => 2,
+    }
+  }
+}
+
+/// a use of a label, replaced with a value calculated from its address once it is placed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Reloc {
+  pub loc: Loc,
+  pub kind: RelocKind,
+  /// added to the address of the label before anything else is calculated
+  pub addend: i16,
+}
+
+impl Reloc {
+  fn new(loc: Loc, kind: RelocKind) -> Self {
+    Self { loc, kind, addend: 0 }
+  }
+
+  /// the value to write given the address of the label and of the reloc itself, which must fit in its size
+  fn value(&self, label: &str, target: u16, at: u16) -> Result<u16, String> {
+    let addr = target as i32 + self.addend as i32;
+    assert!(
+      (0..=0xffff).contains(&addr),
+      "'{}' is outside of memory at 0x{:x}",
+      self.expr(label),
+      addr
+    )?;
+    let addr = addr as u16;
+    let x = match self.kind {
+      RelocKind::Abs | RelocKind::Byte => addr,
+      // the immediate is the last 2 bytes of the instruction
+      RelocKind::PcRel => addr.wrapping_sub(at.wrapping_add(2)),
+      RelocKind::Hi | RelocKind::HiByte => addr >> 8,
+      RelocKind::Lo | RelocKind::LoByte => addr & 0xff,
+    };
+    assert!(
+      self.kind.size() == 2 || x <= u8::MAX as u16,
+      "'{}' is 0x{:04x} which doesn't fit in a byte",
+      self.expr(label),
+      x
+    )?;
+    Ok(x)
+  }
+
+  /// as it would be written as an operand, eg `hi(label + 3)`, with the label as it was named in source
+  pub fn expr(&self, label: &str) -> String {
+    let label = source_name(label);
+    let expr = match self.addend {
+      0 => label.to_string(),
+      n if n < 0 => format!("{} - {}", label, -(n as i32)),
+      n => format!("{} + {}", label, n),
+    };
+    match self.kind {
+      RelocKind::Hi | RelocKind::HiByte => format!("hi({})", expr),
+      RelocKind::Lo | RelocKind::LoByte => format!("lo({})", expr),
+      _ => expr,
+    }
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Display, Debug, FromRepr)]
+#[strum(serialize_all = "snake_case")]
+#[repr(u8)]
+pub enum SectionKind {
+  /// stored in the object and binary
+  Data,
+  /// zero filled, only the length is stored and it is placed after every data section so takes no space in the binary
+  Zero,
+}
+
+impl SectionKind {
+  /// `.bss` and sections beginning with `.bss.` are zero filled
+  pub fn from_name(name: &str) -> Self {
+    match name == ".bss" || name.starts_with(".bss.") {
+      true => Self::Zero,
+      false => Self::Data,
+    }
+  }
+}
+
+pub struct Section {
+  pub name: String,
+  pub kind: SectionKind,
+  /// alignment the start of the section must be placed at
+  pub align: u16,
+  /// zero sections are still filled with zeroes here, but arent written out
   pub data: Vec<u8>,
-  labels: HashMap<String, u16>,
-  label_uses: Vec<(String, u16)>,
+}
+
+impl Section {
+  fn new(name: &str, kind: SectionKind) -> Self {
+    Self {
+      name: name.to_string(),
+      kind,
+      align: 1,
+      data: vec![],
+    }
+  }
+
+  /// pads with zeroes to a multiple of `n`, which must be a power of two
+  fn align(&mut self, n: u16) {
+    self.data.resize(self.data.len().next_multiple_of(n as _), 0);
+    self.align = self.align.max(n);
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Display, Debug, FromRepr)]
+#[strum(serialize_all = "snake_case")]
+#[repr(u8)]
+pub enum Binding {
+  /// only visible to the object it is defined in
+  Local,
+  /// visible to every object it is linked with
+  Global,
+  /// visible to every object, but gives way to a global label of the same name, such as a default handler
+  Weak,
+}
+
+impl Binding {
+  /// whether other objects can reference the label
+  pub fn is_visible(self) -> bool {
+    self != Self::Local
+  }
+}
+
+/// the section and range within it that each section of an object was placed at by `Obj::extend`
+pub type Placement = Vec<(usize, Range<usize>)>;
+
+/// the fixed length header at the start of an object
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Header {
+  pub version: u16,
+  pub flags: u16,
+  pub sections: u16,
+  /// of everything after the header
+  pub checksum: u32,
+}
+
+impl Header {
+  /// `None` for legacy objects, which have no header
+  pub fn parse(data: &[u8]) -> Result<Option<Self>, String> {
+    if data.starts_with(LEGACY_MAGIC) {
+      return Ok(None);
+    }
+    assert!(data.starts_with(MAGIC), "invalid magic bytes")?;
+    let mut r = Reader::new(&data[MAGIC.len()..]);
+    Ok(Some(Self {
+      version: r.u16()?,
+      flags: r.u16()?,
+      sections: r.u16()?,
+      checksum: r.u32()?,
+    }))
+  }
+}
+
+/// a position within one of the sections of an object
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Loc {
+  pub section: usize,
+  pub offset: u16,
+}
+
+/// the source line which the code at a location was assembled from
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SourceLine {
+  pub loc: Loc,
+  /// the number of bytes the line assembled to
+  pub len: u16,
+  /// index into the files of the object
+  pub file: u16,
+  /// zero based
+  pub line: u32,
+}
+
+pub struct Obj {
+  pub sections: Vec<Section>,
+  /// the section being assembled into
+  current: usize,
+  labels: HashMap<String, (Loc, Binding)>,
+  label_uses: Vec<(String, Reloc)>,
+  /// labels declared as defined by another object, which must be by the time the binary is output
+  externs: Vec<String>,
+  /// source files named by the line table
+  files: Vec<String>,
+  lines: Vec<SourceLine>,
+}
+
+impl Default for Obj {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl Obj {
   pub fn new() -> Self {
     Self {
-      data: vec![],
+      sections: vec![Section::new(TEXT, SectionKind::Data)],
+      current: 0,
       labels: HashMap::new(),
       label_uses: vec![],
+      externs: vec![],
+      files: vec![],
+      lines: vec![],
     }
   }
 
+  /// the body after the header is checked against the checksum before anything is parsed
   pub fn load(data: &[u8]) -> Result<Self, String> {
-    assert!(&data[..MAGIC.len()] == MAGIC, "invalid magic bytes")?;
-    let (labels, pos) = parse_table(&data[MAGIC.len()..]);
-    let (label_uses, pos2) = parse_table(&data[MAGIC.len() + pos..]);
-    let data = data[MAGIC.len() + pos + pos2..].to_vec();
+    let Some(Header {
+      version,
+      flags,
+      sections,
+      checksum,
+    }) = Header::parse(data)?
+    else {
+      return Self::load_legacy(&data[LEGACY_MAGIC.len()..]);
+    };
+    assert!(version == VERSION, "unsupported object version {}, expected {}", version, VERSION)?;
+    assert!(flags & !KNOWN_FLAGS == 0, "unsupported object flags 0x{:x}", flags)?;
+    assert!(crc32(&data[HEADER_LEN..]) == checksum, "checksum mismatch, the object is corrupt")?;
+
+    let mut r = Reader::new(&data[HEADER_LEN..]);
+    let obj = Self::load_sections(&mut r, sections)?;
+    assert!(r.is_empty(), "trailing bytes after the last section")?;
+    Ok(obj)
+  }
+
+  /// a single section, which is unaligned, whose labels are all global and only have absolute uses so doesnt store
+  /// their kind
+  fn load_legacy(data: &[u8]) -> Result<Self, String> {
+    let mut r = Reader::new(data);
+    let labels: Vec<(String, u16)> = parse_table(&mut r)?;
+    let label_uses: Vec<(String, u16)> = parse_table(&mut r)?;
+    let mut section = Section::new(TEXT, SectionKind::Data);
+    section.data = r.rest().to_vec();
+    let loc = |offset| Loc { section: 0, offset };
+    Self::from_parts(
+      vec![section],
+      labels.into_iter().map(|(l, offset)| (l, (loc(offset), Binding::Global))).collect(),
+      label_uses
+        .into_iter()
+        .map(|(l, offset)| (l, Reloc::new(loc(offset), RelocKind::Abs)))
+        .collect(),
+      vec![],
+    )
+  }
+
+  /// each section followed by the tables of labels, label uses and externs, and then the line table
+  fn load_sections(r: &mut Reader, count: u16) -> Result<Self, String> {
+    let mut sections = vec![];
+    for _ in 0..count {
+      let name = r.str()?;
+      let kind = match SectionKind::from_repr(r.u8()?) {
+        Some(kind) => kind,
+        None => return err!("invalid kind for section '{}'", name),
+      };
+      let mut section = Section::new(&name, kind);
+      section.align = r.u16()?;
+      let len = r.u32()? as usize;
+      // checked here as zero filled sections are allocated from just the length
+      assert!(len <= 0x10000, "section '{}' is too large", name)?;
+      section.data = match kind {
+        SectionKind::Data => r.bytes(len)?.to_vec(),
+        SectionKind::Zero => vec![0; len],
+      };
+      sections.push(section);
+    }
+    let labels = parse_table(r)?;
+    let label_uses = parse_table(r)?;
+    let externs = parse_table::<()>(r)?.into_iter().map(|(l, _)| l).collect();
+    let mut obj = Self::from_parts(sections, labels, label_uses, externs)?;
+    obj.files = parse_table::<()>(r)?.into_iter().map(|(f, _)| f).collect();
+    for _ in 0..r.u32()? {
+      let line = SourceLine {
+        loc: Loc::parse(r)?,
+        len: r.u16()?,
+        file: r.u16()?,
+        line: r.u32()?,
+      };
+      let in_bounds = obj
+        .sections
+        .get(line.loc.section)
+        .is_some_and(|s| line.loc.offset as usize + line.len as usize <= s.data.len());
+      assert!(
+        in_bounds && (line.file as usize) < obj.files.len(),
+        "line table entry for line {} is out of bounds",
+        line.line + 1
+      )?;
+      obj.lines.push(line);
+    }
+    Ok(obj)
+  }
+
+  fn from_parts(
+    sections: Vec<Section>,
+    labels: Vec<(String, (Loc, Binding))>,
+    label_uses: Vec<(String, Reloc)>,
+    externs: Vec<String>,
+  ) -> Result<Self, String> {
+    assert!(
+      sections.first().is_some_and(|s| s.name == TEXT),
+      "the first section must be '{}'",
+      TEXT
+    )?;
+    for s in &sections {
+      assert!(s.align.is_power_of_two(), "invalid alignment {} for section '{}'", s.align, s.name)?;
+      assert!(s.data.len() <= 0x10000, "section '{}' is too large", s.name)?;
+    }
+    let in_bounds = |loc: &Loc, len: usize| sections.get(loc.section).is_some_and(|s| loc.offset as usize + len <= s.data.len());
+    for (label, (loc, _)) in &labels {
+      assert!(in_bounds(loc, 0), "label '{}' is outside of its section", label)?;
+    }
+    for (label, r) in &label_uses {
+      assert!(
+        in_bounds(&r.loc, r.kind.size()),
+        "reference to '{}' is outside of its section",
+        label
+      )?;
+      assert!(
+        sections[r.loc.section].kind == SectionKind::Data,
+        "reference to '{}' in zero filled section",
+        label
+      )?;
+    }
     Ok(Self {
+      sections,
+      current: 0,
       labels: HashMap::from_iter(labels),
       label_uses,
-      data,
+      externs,
+      files: vec![],
+      lines: vec![],
     })
   }
 
-  pub fn insert_label(&mut self, label: String) -> Result<(), String> {
-    match self.labels.try_insert(label, self.data.len() as _) {
+  /// the section being assembled into
+  pub fn section(&mut self) -> &mut Section {
+    &mut self.sections[self.current]
+  }
+
+  pub fn current_section(&self) -> usize {
+    self.current
+  }
+
+  /// switches to the section with this name, creating it if it doesnt exist
+  pub fn switch_section(&mut self, name: &str) -> Result<(), String> {
+    self.current = self.find_or_insert_section(name, SectionKind::from_name(name))?;
+    Ok(())
+  }
+
+  fn find_or_insert_section(&mut self, name: &str, kind: SectionKind) -> Result<usize, String> {
+    match self.sections.iter().position(|s| s.name == name) {
+      Some(i) if self.sections[i].kind == kind => Ok(i),
+      Some(_) => err!("section '{}' is declared with different kinds", name),
+      None => {
+        self.sections.push(Section::new(name, kind));
+        Ok(self.sections.len() - 1)
+      }
+    }
+  }
+
+  fn here(&self) -> Loc {
+    Loc {
+      section: self.current,
+      offset: self.sections[self.current].data.len() as _,
+    }
+  }
+
+  pub fn insert_label(&mut self, label: String, binding: Binding) -> Result<(), String> {
+    let loc = self.here();
+    match self.labels.try_insert(label, (loc, binding)) {
       Ok(_) => Ok(()),
       Err(e) => err!("label '{}' already declared", e.entry.key()),
     }
   }
 
-  pub fn insert_label_usage(&mut self, label: String, offset: usize) {
-    self.label_uses.push((label, (self.data.len() + offset) as _));
+  /// labels and where they are defined
+  pub fn labels(&self) -> impl Iterator<Item = (&str, Loc)> {
+    self.labels.iter().map(|(label, (loc, _))| (label.as_str(), *loc))
+  }
+
+  pub fn binding(&self, label: &str) -> Option<Binding> {
+    self.labels.get(label).map(|(_, binding)| *binding)
+  }
+
+  pub fn set_binding(&mut self, label: &str, binding: Binding) {
+    if let Some((_, b)) = self.labels.get_mut(label) {
+      *b = binding;
+    }
+  }
+
+  pub fn externs(&self) -> impl Iterator<Item = &str> {
+    self.externs.iter().map(|l| l.as_str())
+  }
+
+  pub fn insert_extern(&mut self, label: String) {
+    if !self.externs.contains(&label) {
+      self.externs.push(label);
+    }
+  }
+
+  /// labels referenced, the location of the address to be replaced and how it is calculated
+  pub fn label_uses(&self) -> impl Iterator<Item = (&str, Reloc)> {
+    self.label_uses.iter().map(|(label, r)| (label.as_str(), *r))
+  }
+
+  /// empties a section and forgets the labels in it, along with the relocations and externs only it needed
+  pub fn clear_section(&mut self, section: usize) {
+    self.sections[section].data.clear();
+    self.labels.retain(|_, (loc, _)| loc.section != section);
+    self.label_uses.retain(|(_, r)| r.loc.section != section);
+    self.lines.retain(|l| l.loc.section != section);
+    let uses = &self.label_uses;
+    self.externs.retain(|l| uses.iter().any(|(u, _)| u == l));
+  }
+
+  /// records that the code from `loc` onwards was assembled from a line of `file`
+  pub fn insert_line(&mut self, file: &str, loc: Loc, len: usize, line: usize) {
+    let file = self.find_or_insert_file(file);
+    self.lines.push(SourceLine {
+      loc,
+      len: len as u16,
+      file,
+      line: line as u32,
+    });
+  }
+
+  /// the line table, with the file each line is from
+  pub fn lines(&self) -> impl Iterator<Item = (SourceLine, &str)> {
+    self.lines.iter().map(|l| (*l, self.files[l.file as usize].as_str()))
+  }
+
+  fn find_or_insert_file(&mut self, file: &str) -> u16 {
+    match self.files.iter().position(|f| f == file) {
+      Some(i) => i as u16,
+      None => {
+        self.files.push(file.to_string());
+        self.files.len() as u16 - 1
+      }
+    }
+  }
+
+  /// labels which are used or declared extern but not defined, in order
+  pub fn undefined(&self) -> Vec<&str> {
+    let uses = self.label_uses.iter().map(|(l, _)| l);
+    let mut undefined: Vec<_> = uses
+      .chain(&self.externs)
+      .filter(|l| !self.has_label(l))
+      .map(|l| l.as_str())
+      .collect();
+    undefined.sort();
+    undefined.dedup();
+    undefined
+  }
+
+  pub fn has_label(&self, label: &str) -> bool {
+    self.labels.contains_key(label)
+  }
+
+  pub fn insert_label_usage(&mut self, label: String, offset: usize, kind: RelocKind, addend: i16) {
+    let mut loc = self.here();
+    loc.offset += offset as u16;
+    self.label_uses.push((label, Reloc { loc, kind, addend }));
   }
 
   pub fn emit_instr(&mut self, instr: Instruction) {
-    self.data.extend(instr.as_u32().to_le_bytes());
+    self.section().data.extend(instr.as_u32().to_le_bytes());
   }
 
-  pub fn extend(&mut self, other: Self) -> Result<(), String> {
-    for (label, addr) in other.labels {
-      match self.labels.entry(label.clone()) {
-        Entry::Occupied(_) => return err!("duplicate label '{}'", label),
+  /// pads the current section with zeroes to a multiple of `n`, which must be a power of two
+  pub fn align(&mut self, n: u16) {
+    self.section().align(n);
+  }
+
+  /// appends each section of `other` to the section of the same name
+  pub fn extend(&mut self, other: Self) -> Result<Placement, String> {
+    // where each section of other has been placed
+    let mut placed = vec![];
+    for section in other.sections {
+      let i = self.find_or_insert_section(&section.name, section.kind)?;
+      // offsets within other are only aligned relative to the start of its sections
+      self.sections[i].align(section.align);
+      let start = self.sections[i].data.len();
+      assert!(start + section.data.len() <= 0x10000, "section '{}' is too large", section.name)?;
+      self.sections[i].data.extend(section.data);
+      placed.push((i, start, self.sections[i].data.len(), section.name));
+    }
+    // a label at the very end of a full section has no offset
+    let place = |loc: Loc| {
+      let (section, start, _, name) = &placed[loc.section];
+      match u16::try_from(start + loc.offset as usize) {
+        Ok(offset) => Ok(Loc { section: *section, offset }),
+        Err(_) => err!("section '{}' is too large", name),
+      }
+    };
+    // local labels are renamed so that they cant clash with, or be referenced by, labels in any other object
+    let mut other_uses = other.label_uses;
+    for (label, (loc, binding)) in other.labels {
+      let name = match binding {
+        Binding::Local => {
+          let name = (1..)
+            .map(|n| format!("{}{}{}", label, RENAME_SEP, n))
+            .find(|n| !self.labels.contains_key(n))
+            .unwrap();
+          for (l, _) in other_uses.iter_mut().filter(|(l, _)| *l == label) {
+            l.clone_from(&name);
+          }
+          name
+        }
+        Binding::Global | Binding::Weak => label,
+      };
+      match self.labels.entry(name.clone()) {
+        // the first weak definition is kept until a global one replaces it
+        Entry::Occupied(mut e) => match (e.get().1, binding) {
+          (Binding::Weak, Binding::Global) => {
+            e.insert((place(loc)?, binding));
+          }
+          (Binding::Global | Binding::Weak, Binding::Weak) => {}
+          _ => return err!("duplicate label '{}'", name),
+        },
         Entry::Vacant(e) => {
-          e.insert(self.data.len() as u16 + addr);
+          e.insert((place(loc)?, binding));
         }
       }
     }
-    for (label, addr) in other.label_uses {
-      self.label_uses.push((label, self.data.len() as u16 + addr));
+    for (label, r) in other_uses {
+      self.label_uses.push((label, Reloc { loc: place(r.loc)?, ..r }));
     }
-    self.data.extend(other.data);
-    Ok(())
+    for label in other.externs {
+      self.insert_extern(label);
+    }
+    for line in other.lines {
+      let file = self.find_or_insert_file(&other.files[line.file as usize]);
+      self.lines.push(SourceLine {
+        loc: place(line.loc)?,
+        file,
+        ..line
+      });
+    }
+    Ok(placed.into_iter().map(|(i, start, end, _)| (i, start..end)).collect())
+  }
+
+  /// the address of each section, sections with a fixed address are placed first
+  /// the rest are placed in order from the base address, data sections then zero sections, skipping over fixed ones
+  pub fn layout(&self, config: &LinkConfig) -> Result<Vec<u16>, String> {
+    let mut ranges: Vec<Option<Range<usize>>> = vec![None; self.sections.len()];
+    for (i, s) in self.sections.iter().enumerate() {
+      if let Some(addr) = config.address(&s.name) {
+        assert!(
+          addr % s.align == 0,
+          "section '{}' at 0x{:04x} must be aligned to {}",
+          s.name,
+          addr,
+          s.align
+        )?;
+        ranges[i] = Some(addr as usize..addr as usize + s.data.len());
+      }
+    }
+    let fixed: Vec<_> = ranges.iter().flatten().filter(|r| !r.is_empty()).cloned().collect();
+    let mut addr = config.base as usize;
+    for kind in [SectionKind::Data, SectionKind::Zero] {
+      for (i, s) in self.sections.iter().enumerate() {
+        if s.kind != kind || ranges[i].is_some() {
+          continue;
+        }
+        addr = addr.next_multiple_of(s.align as usize);
+        while let Some(r) = fixed
+          .iter()
+          .find(|r| !s.data.is_empty() && r.start < addr + s.data.len() && addr < r.end)
+        {
+          addr = r.end.next_multiple_of(s.align as usize);
+        }
+        ranges[i] = Some(addr..addr + s.data.len());
+        addr += s.data.len();
+      }
+    }
+
+    let ranges: Vec<_> = ranges.into_iter().flatten().collect();
+    for (s, r) in self.sections.iter().zip(&ranges) {
+      assert!(r.end <= 0x10000, "section '{}' doesn't fit in memory", s.name)?;
+    }
+    let named: Vec<_> = self.sections.iter().map(|s| s.name.as_str()).zip(ranges.iter().cloned()).collect();
+    link::check_overlaps(&named)?;
+    Ok(ranges.into_iter().map(|r| r.start as u16).collect())
   }
 
   pub fn out_obj(self) -> Vec<u8> {
+    let mut body = vec![];
+    for s in &self.sections {
+      body.extend(s.name.as_bytes());
+      body.push(0);
+      body.push(s.kind as u8);
+      body.extend(s.align.to_le_bytes());
+      body.extend((s.data.len() as u32).to_le_bytes());
+      if s.kind == SectionKind::Data {
+        body.extend(&s.data);
+      }
+    }
+    out_table(&mut body, self.labels.into_iter());
+    out_table(&mut body, self.label_uses.into_iter());
+    out_table(&mut body, self.externs.into_iter().map(|l| (l, ())));
+    out_table(&mut body, self.files.into_iter().map(|f| (f, ())));
+    body.extend((self.lines.len() as u32).to_le_bytes());
+    for l in self.lines {
+      l.loc.out(&mut body);
+      body.extend(l.len.to_le_bytes());
+      body.extend(l.file.to_le_bytes());
+      body.extend(l.line.to_le_bytes());
+    }
+
     let mut out = Vec::from(MAGIC);
-    out_table(&mut out, self.labels.into_iter());
-    out_table(&mut out, self.label_uses.into_iter());
-    out.extend(self.data);
+    out.extend(VERSION.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    out.extend((self.sections.len() as u16).to_le_bytes());
+    out.extend(crc32(&body).to_le_bytes());
+    out.extend(body);
     out
   }
 
-  pub fn out_bin(mut self) -> Result<Vec<u8>, String> {
-    for (label, replace) in &self.label_uses {
-      match self.labels.get(label) {
-        Some(addr) => {
-          self.data.splice(*replace as usize..*replace as usize + 2, addr.to_le_bytes());
-        }
+  /// the image starts at address 0, where it is loaded, and ends at the last byte of any data section
+  pub fn out_bin(self, config: &LinkConfig) -> Result<Vec<u8>, String> {
+    Ok(image::encode(&self.out_image(config)?, Format::Bin))
+  }
+
+  /// the data sections at the addresses they are placed at, in order of address, with every label use replaced
+  pub fn out_image(mut self, config: &LinkConfig) -> Result<Vec<Segment>, String> {
+    for label in &self.externs {
+      assert!(
+        self.binding(label).is_some_and(Binding::is_visible),
+        "undefined extern '{}', it must be defined as a global or weak label in another object",
+        label
+      )?;
+    }
+    let addrs = self.layout(config)?;
+    let addr_of = |loc: Loc| addrs[loc.section].wrapping_add(loc.offset);
+    for (label, r) in &self.label_uses {
+      let addr = match self.labels.get(label) {
+        Some((l, _)) => addr_of(*l),
         None => return err!("undefined label '{}'", label),
+      };
+      let x = r.value(label, addr, addr_of(r.loc))?;
+      let replace = r.loc.offset as usize;
+      self.sections[r.loc.section].data[replace..replace + r.kind.size()].copy_from_slice(&x.to_le_bytes()[..r.kind.size()]);
+    }
+
+    let mut segments: Vec<_> = self
+      .sections
+      .into_iter()
+      .zip(addrs)
+      .filter(|(s, _)| s.kind == SectionKind::Data && !s.data.is_empty())
+      .map(|(s, addr)| (addr, s.data))
+      .collect();
+    segments.sort_by_key(|(addr, _)| *addr);
+    Ok(segments)
+  }
+}
+
+/// separates a local label renamed by `Obj::extend` from the number which makes it unique, names are nul terminated
+/// in object files so it cant appear in one, either there or in source
+const RENAME_SEP: char = '\0';
+
+/// the name a label was given in source, before it was renamed by `Obj::extend`
+pub fn source_name(label: &str) -> &str {
+  label.split_once(RENAME_SEP).map_or(label, |(name, _)| name)
+}
+
+/// bounds checked reading of an object file
+pub(crate) struct Reader<'a> {
+  bin: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Reader<'a> {
+  pub(crate) fn new(bin: &'a [u8]) -> Self {
+    Self { bin, pos: 0 }
+  }
+
+  pub(crate) fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+    match self.bin.get(self.pos..self.pos + n) {
+      Some(bytes) => {
+        self.pos += n;
+        Ok(bytes)
       }
+      None => err!("unexpected end of object file"),
+    }
+  }
+
+  pub(crate) fn u8(&mut self) -> Result<u8, String> {
+    Ok(self.bytes(1)?[0])
+  }
+
+  pub(crate) fn u16(&mut self) -> Result<u16, String> {
+    Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+  }
+
+  pub(crate) fn u32(&mut self) -> Result<u32, String> {
+    Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+  }
+
+  /// a nul terminated utf-8 string
+  pub(crate) fn str(&mut self) -> Result<String, String> {
+    let len = match self.bin[self.pos..].iter().position(|b| *b == 0) {
+      Some(len) => len,
+      None => return err!("unterminated string in object file"),
+    };
+    let bytes = self.bytes(len + 1)?;
+    match String::from_utf8(bytes[..len].to_vec()) {
+      Ok(s) => Ok(s),
+      Err(_) => err!("invalid utf-8 in object file"),
+    }
+  }
+
+  pub(crate) fn rest(&mut self) -> &'a [u8] {
+    let rest = &self.bin[self.pos..];
+    self.pos = self.bin.len();
+    rest
+  }
+
+  pub(crate) fn is_empty(&self) -> bool {
+    self.pos == self.bin.len()
+  }
+}
+
+/// a value stored against each name in an object file table
+pub(crate) trait TableValue: Sized {
+  fn out(&self, out: &mut Vec<u8>);
+  fn parse(r: &mut Reader) -> Result<Self, String>;
+}
+
+impl TableValue for u16 {
+  fn out(&self, out: &mut Vec<u8>) {
+    out.extend(self.to_le_bytes());
+  }
+
+  fn parse(r: &mut Reader) -> Result<Self, String> {
+    r.u16()
+  }
+}
+
+impl TableValue for Loc {
+  fn out(&self, out: &mut Vec<u8>) {
+    (self.section as u16).out(out);
+    self.offset.out(out);
+  }
+
+  fn parse(r: &mut Reader) -> Result<Self, String> {
+    Ok(Loc {
+      section: r.u16()? as _,
+      offset: r.u16()?,
+    })
+  }
+}
+
+impl TableValue for (Loc, Binding) {
+  fn out(&self, out: &mut Vec<u8>) {
+    self.0.out(out);
+    out.push(self.1 as u8);
+  }
+
+  fn parse(r: &mut Reader) -> Result<Self, String> {
+    let loc = Loc::parse(r)?;
+    match Binding::from_repr(r.u8()?) {
+      Some(binding) => Ok((loc, binding)),
+      None => err!("invalid symbol binding"),
     }
-    Ok(self.data)
   }
 }
 
-fn out_table<I: ExactSizeIterator<Item = (String, u16)>>(out: &mut Vec<u8>, iter: I) {
+/// for tables which are just a list of names
+impl TableValue for () {
+  fn out(&self, _: &mut Vec<u8>) {}
+
+  fn parse(_: &mut Reader) -> Result<Self, String> {
+    Ok(())
+  }
+}
+
+impl TableValue for Reloc {
+  fn out(&self, out: &mut Vec<u8>) {
+    (self.loc, self.kind).out(out);
+    (self.addend as u16).out(out);
+  }
+
+  fn parse(r: &mut Reader) -> Result<Self, String> {
+    let (loc, kind) = <(Loc, RelocKind)>::parse(r)?;
+    Ok(Self {
+      loc,
+      kind,
+      addend: r.u16()? as i16,
+    })
+  }
+}
+
+impl<T: TableValue> TableValue for (T, RelocKind) {
+  fn out(&self, out: &mut Vec<u8>) {
+    self.0.out(out);
+    out.push(self.1 as u8);
+  }
+
+  fn parse(r: &mut Reader) -> Result<Self, String> {
+    let x = T::parse(r)?;
+    match RelocKind::from_repr(r.u8()?) {
+      Some(kind) => Ok((x, kind)),
+      None => err!("invalid relocation kind"),
+    }
+  }
+}
+
+pub(crate) fn out_table<V: TableValue, I: ExactSizeIterator<Item = (String, V)>>(out: &mut Vec<u8>, iter: I) {
   out.extend((iter.len() as u16).to_le_bytes());
   for (k, v) in iter {
     out.extend(k.as_bytes());
     out.push(0);
-    out.extend(v.to_le_bytes());
+    v.out(out);
   }
 }
 
-fn parse_table(bin: &[u8]) -> (Vec<(String, u16)>, usize) {
-  let len = u16::from_le_bytes([bin[0], bin[1]]);
-  let mut pos = 2;
-  let mut out = Vec::with_capacity(len as _);
+pub(crate) fn parse_table<V: TableValue>(r: &mut Reader) -> Result<Vec<(String, V)>, String> {
+  let len = r.u16()?;
+  let mut out = vec![];
   for _ in 0..len {
-    let strlen = &bin[pos..].iter().position(|b| *b == 0).unwrap();
-    out.push((
-      String::from_utf8(bin[pos..pos + strlen].to_vec()).unwrap(),
-      u16::from_le_bytes([bin[pos + strlen + 1], bin[pos + strlen + 2]]),
-    ));
-    pos += strlen + 3;
-  }
-  (out, pos)
+    let name = r.str()?;
+    out.push((name, V::parse(r)?));
+  }
+  Ok(out)
+}
+
+/// crc-32 as used by zip and png
+pub(crate) fn crc32(bin: &[u8]) -> u32 {
+  let mut crc = !0u32;
+  for b in bin {
+    crc ^= *b as u32;
+    for _ in 0..8 {
+      crc = match crc & 1 {
+        1 => (crc >> 1) ^ 0xedb88320,
+        _ => crc >> 1,
+      };
+    }
+  }
+  !crc
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::asm::{self, Assembler};
+
+  #[test]
+  fn test_extend_alignment() {
+    let mut a = Obj::new();
+    a.section().data.push(1);
+    let mut b = Obj::new();
+    b.align(4);
+    b.insert_label("b".to_string(), Binding::Global).unwrap();
+    b.section().data.extend([2, 3]);
+
+    let b = Obj::load(&b.out_obj()).unwrap();
+    a.extend(b).unwrap();
+    assert_eq!(a.labels["b"], (Loc { section: 0, offset: 4 }, Binding::Global));
+    assert_eq!(a.sections[0].align, 4);
+    assert_eq!(a.out_bin(&LinkConfig::default()).unwrap(), [1, 0, 0, 0, 2, 3]);
+
+    // a label which would be placed past the end of a full section
+    let mut c = Obj::new();
+    c.section().data.resize(4, 0);
+    let mut d = Obj::new();
+    d.section().data.resize(0xfffc, 0);
+    d.insert_label("end".to_string(), Binding::Global).unwrap();
+    assert_eq!(c.extend(d).err().unwrap(), "section '.text' is too large");
+  }
+
+  #[test]
+  fn test_sections() {
+    let assemble = |src| Obj::load(&asm::assemble(src).out_obj()).unwrap();
+    let mut a = assemble(".bss\nbuf:\n.skip 4\n.data\n.db 1\n.text\nla %r1, buf\n");
+    let b = assemble(".data\nvalue:\n.db 2\n.bss\n.skip 2\ntop:\n.text\nla %r2, value\nla %r3, top\n");
+    a.extend(b).unwrap();
+    let names: Vec<_> = a.sections.iter().map(|s| (s.name.as_str(), s.data.len())).collect();
+    assert_eq!(names, [(".text", 12), (".bss", 6), (".data", 2)]);
+    // data sections come first, and zero sections take no space
+    assert_eq!(a.layout(&LinkConfig::default()).unwrap(), [0, 14, 12]);
+    // unfixed sections flow around fixed ones
+    let config = LinkConfig::parse("base 0x10\nsection .data 0x18\n").unwrap();
+    assert_eq!(a.layout(&config).unwrap(), [0x1a, 0x26, 0x18]);
+    let config = LinkConfig::parse("section .bss 0xbffc\n").unwrap();
+    std::assert!(a.layout(&config).is_err());
+    let bin = a.out_bin(&LinkConfig::default()).unwrap();
+    assert_eq!(bin.len(), 14);
+    assert_eq!(bin[2..4], 14u16.to_le_bytes());
+    assert_eq!(bin[6..8], 13u16.to_le_bytes());
+    assert_eq!(bin[10..12], 20u16.to_le_bytes());
+    assert_eq!(bin[12..], [1, 2]);
+    // a raw binary is loaded at 0, so it is zero filled up to the base
+    let bin = asm::assemble(".data\n.db 1\n.text\n.dw 7\n")
+      .out_bin(&LinkConfig::parse("base 0x10\n").unwrap())
+      .unwrap();
+    assert_eq!(bin.len(), 0x13);
+    std::assert!(bin[..0x10].iter().all(|b| *b == 0));
+    assert_eq!(bin[0x10..], [7, 0, 1]);
+  }
+
+  #[test]
+  fn test_bindings() {
+    let assemble = |src| Obj::load(&asm::assemble(src).out_obj()).unwrap();
+    let mut a = assemble(".extern util\n.local helper\nstart:\n  jmp helper\nhelper:\n  jmp util\n");
+    let b = assemble(".local helper\nhelper:\n  .skip 4\nutil:\n  jmp helper\n");
+    std::assert!(assemble(".extern util\n  jmp util\n").out_bin(&LinkConfig::default()).is_err());
+    assert_eq!(a.binding("helper"), Some(Binding::Local));
+    assert_eq!(a.externs().collect::<Vec<_>>(), ["util"]);
+
+    // local labels dont clash, and references within each object stay within it
+    a.extend(b).unwrap();
+    assert_eq!(a.labels["helper"].0.offset, 4);
+    let renamed = format!("helper{}1", RENAME_SEP);
+    assert_eq!(a.labels[&renamed].0.offset, 8);
+    assert_eq!(source_name(&renamed), "helper");
+    let bin = a.out_bin(&LinkConfig::default()).unwrap();
+    assert_eq!(bin[2..4], 4u16.to_le_bytes());
+    assert_eq!(bin[6..8], 12u16.to_le_bytes());
+    assert_eq!(bin[14..16], 8u16.to_le_bytes());
+
+    let mut c = assemble("util:\n");
+    std::assert!(c.extend(assemble("util:\n")).is_err());
+
+    // a global which the assembler wouldnt allow, but could be in an object from elsewhere, doesnt clash either
+    let mut d = Obj::new();
+    d.extend(assemble(".local x\nx:\n  jmp x\n")).unwrap();
+    let mut e = Obj::new();
+    e.insert_label("x:1".to_string(), Binding::Global).unwrap();
+    d.extend(e).unwrap();
+    assert_eq!(d.binding("x:1"), Some(Binding::Global));
+    assert_eq!(d.out_bin(&LinkConfig::default()).unwrap()[2..4], 0u16.to_le_bytes());
+
+    // a global definition replaces a weak one wherever it is linked, and only the first of two weak ones is kept
+    let mut f = assemble(".weak handler\nhandler:\n  hlt\n");
+    f.extend(assemble(".weak handler\n  nop\nhandler:\n  nop\n")).unwrap();
+    assert_eq!(f.labels["handler"], (Loc { section: 0, offset: 0 }, Binding::Weak));
+    f.extend(assemble("  nop\nhandler:\n  nop\n")).unwrap();
+    assert_eq!(f.labels["handler"], (Loc { section: 0, offset: 16 }, Binding::Global));
+    f.extend(assemble(".weak handler\nhandler:\n")).unwrap();
+    assert_eq!(f.labels["handler"].1, Binding::Global);
+    std::assert!(f.extend(assemble("handler:\n")).is_err());
+  }
+
+  #[test]
+  fn test_relocations() {
+    let assemble = |src: &str| Obj::load(&asm::assemble(src).out_obj()).unwrap();
+    let link = |padding: usize, src: &str| {
+      let mut obj = Obj::new();
+      obj.section().data.resize(padding, 0);
+      obj
+        .extend(assemble(
+          ".extern table\n  mov %r1, hi(table + 2)\n  mov %r2, table - 1\n  .db lo(table)\n",
+        ))
+        .unwrap();
+      obj.extend(assemble(src)).unwrap();
+      obj.out_bin(&LinkConfig::default()).map(|bin| bin[padding..].to_vec())
+    };
+
+    let bin = link(0, "table:\n  .db table\n").unwrap();
+    assert_eq!(bin.len(), 10);
+    assert_eq!(bin[2..4], [0, 0]);
+    assert_eq!(bin[6..8], 8u16.to_le_bytes());
+    assert_eq!(bin[8..], [9, 9]);
+    let bin = link(0x2f5, "table:\n").unwrap();
+    assert_eq!(bin[2..4], 3u16.to_le_bytes());
+    assert_eq!(bin[8], 0xfe);
+    // only the absolute address needs to fit in a byte
+    assert_eq!(
+      link(0xf7, "table:\n  .db table\n").err().unwrap(),
+      "'table' is 0x0100 which doesn't fit in a byte"
+    );
+    let reloc = Reloc {
+      loc: Loc { section: 0, offset: 0 },
+      kind: RelocKind::Hi,
+      addend: 2,
+    };
+    assert_eq!(
+      reloc.value("table", 0xfffe, 0).err().unwrap(),
+      "'hi(table + 2)' is outside of memory at 0x10000"
+    );
+    let mut a = Obj::new();
+    a.insert_label_usage("a".to_string(), 0, RelocKind::Lo, -1);
+    a.insert_label("a".to_string(), Binding::Global).unwrap();
+    a.section().data.extend([0, 0]);
+    let obj = Obj::load(&a.out_obj()).unwrap();
+    assert_eq!(obj.label_uses().next().unwrap().1.expr("a"), "lo(a - 1)");
+  }
+
+  #[test]
+  fn test_pc_relative() {
+    let src = "start:\n  beq start\n  lw %r1, %pc, start\n  jmp start\n";
+    let link = |padding: usize| {
+      let mut assembler = Assembler::new();
+      assembler.assemble(src).unwrap();
+      let mut obj = Obj::new();
+      obj.section().data.resize(padding, 0);
+      obj.extend(assembler.obj).unwrap();
+      obj.out_bin(&LinkConfig::default()).unwrap()[padding..].to_vec()
+    };
+
+    let bin = link(0);
+    assert_eq!(
+      Instruction::from_u32(u32::from_le_bytes(bin[0..4].try_into().unwrap()))
+        .unwrap()
+        .imm(),
+      Some(-4i16 as u16)
+    );
+    assert_eq!(
+      Instruction::from_u32(u32::from_le_bytes(bin[4..8].try_into().unwrap()))
+        .unwrap()
+        .imm(),
+      Some(-8i16 as u16)
+    );
+    // only the absolute jump changes when the code is moved
+    let moved = link(0x100);
+    assert_eq!(bin[..8], moved[..8]);
+    assert_ne!(bin[8..], moved[8..]);
+  }
+
+  #[test]
+  fn test_load_errors() {
+    let mut obj = Obj::new();
+    obj.insert_label("start".to_string(), Binding::Global).unwrap();
+    obj.section().data.extend([1, 2, 3, 4]);
+    obj.insert_label_usage("start".to_string(), 0, RelocKind::Abs, 0);
+    obj.section().data.extend([0, 0]);
+    obj.switch_section(".bss").unwrap();
+    obj.section().data.extend([0; 4]);
+    let bin = obj.out_obj();
+    std::assert!(Obj::load(&bin).is_ok());
+    let header = Header::parse(&bin).unwrap().unwrap();
+    assert_eq!((header.version, header.flags, header.sections), (VERSION, 0, 2));
+    assert_eq!(crc32(b"123456789"), 0xcbf43926);
+
+    for len in 0..bin.len() {
+      std::assert!(Obj::load(&bin[..len]).is_err());
+    }
+    let mut corrupt = bin.clone();
+    *corrupt.last_mut().unwrap() ^= 1;
+    assert_eq!(Obj::load(&corrupt).err().unwrap(), "checksum mismatch, the object is corrupt");
+    let mut future = bin.clone();
+    future[MAGIC.len()] = VERSION as u8 + 1;
+    std::assert!(Obj::load(&future).err().unwrap().starts_with("unsupported object version"));
+    std::assert!(Obj::load(b"q15").is_err());
+
+    // zero filled sections are checked before they are allocated
+    let mut sections = Vec::from(b".text\0\0\x01\0\0\0\0\0");
+    sections.extend(b".bss\0\x01\x01\0\xff\xff\xff\xff");
+    let huge = Obj::load_sections(&mut Reader::new(&sections), 2);
+    assert_eq!(huge.err().unwrap(), "section '.bss' is too large");
+
+    // the kind of a label use is checked rather than trusted
+    let kind = <(Loc, RelocKind)>::parse(&mut Reader::new(&[0, 0, 0, 0, 7]));
+    assert_eq!(kind.err().unwrap(), "invalid relocation kind");
+  }
+
+  #[test]
+  fn test_load_legacy() {
+    // written by the assembler before the header was added, from "start:\n  mov %r1, 5\n  jmp start\ndata:\n  .dw data\n"
+    let bin =
+      b"q16\x02\x00data\x00\x08\x00start\x00\x00\x00\x02\x00start\x00\x06\x00data\x00\x08\x00\x81\x01\x05\x00\x81\x09\x00\x00\x00\x00";
+    assert_eq!(Header::parse(bin), Ok(None));
+    let obj = Obj::load(bin).unwrap();
+    assert_eq!(obj.sections[0].align, 1);
+    assert_eq!(
+      obj.out_bin(&LinkConfig::default()).unwrap(),
+      [0x81, 0x01, 0x05, 0x00, 0x81, 0x09, 0x00, 0x00, 0x08, 0x00]
+    );
+    // truncated within the tables
+    std::assert!(Obj::load(&bin[..20]).is_err());
+  }
 }
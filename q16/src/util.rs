@@ -1,4 +1,5 @@
 use std::{process, mem, env};
+use std::ops::Range;
 use owo_colors::OwoColorize;
 
 /// prints an error message with optional context of where and what went wrong
@@ -15,6 +16,73 @@ pub fn err_msg(msg: &str, ctx: Option<(&str, &str)>) -> ! {
   process::exit(1)
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+/// a message attached to a span of a line of source code
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub msg: String,
+  /// zero indexed
+  pub line: usize,
+  /// byte range within the line
+  pub span: Range<usize>,
+  pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+  pub fn error(msg: String, line: usize, span: Range<usize>) -> Self {
+    Self {
+      severity: Severity::Error,
+      msg,
+      line,
+      span,
+      notes: vec![],
+    }
+  }
+
+  pub fn warning(msg: String, line: usize, span: Range<usize>) -> Self {
+    Self {
+      severity: Severity::Warning,
+      ..Self::error(msg, line, span)
+    }
+  }
+
+  pub fn note(mut self, note: String) -> Self {
+    self.notes.push(note);
+    self
+  }
+
+  /// prints in the style of rustc, underlining the span within the offending line of `src`
+  pub fn print(&self, path: &str, src: &str) {
+    let line = src.lines().nth(self.line).unwrap_or_default();
+    let col = line[..self.span.start].chars().count();
+    let len = line[self.span.clone()].chars().count().max(1);
+    let gutter = " ".repeat((self.line + 1).to_string().len());
+    let marker = "^".repeat(len);
+
+    match self.severity {
+      Severity::Error => println!("{} {}", "error:".red().bold(), self.msg.bold()),
+      Severity::Warning => println!("{} {}", "warning:".yellow().bold(), self.msg.bold()),
+    }
+    println!("{}{} {}:{}:{}", gutter, "-->".blue(), path, self.line + 1, col + 1);
+    println!("{} {}", gutter, "|".blue());
+    println!("{} {} {}", (self.line + 1).blue(), "|".blue(), line);
+    match self.severity {
+      Severity::Error => println!("{} {} {}{}", gutter, "|".blue(), " ".repeat(col), marker.red().bold()),
+      Severity::Warning => println!("{} {} {}{}", gutter, "|".blue(), " ".repeat(col), marker.yellow().bold()),
+    }
+    for note in &self.notes {
+      println!("{} {} {} {}", gutter, "=".blue(), "note:".bold(), note);
+    }
+    println!();
+  }
+}
+
 /// fixed size, used for efficiently tracking emulation speed
 pub struct CircularBuffer<T, const N: usize> {
   buf: [T; N],
@@ -22,6 +90,12 @@ pub struct CircularBuffer<T, const N: usize> {
   len: usize,
 }
 
+impl<T, const N: usize> Default for CircularBuffer<T, N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl<T, const N: usize> CircularBuffer<T, N> {
   pub fn new() -> Self {
     Self {
@@ -41,6 +115,10 @@ impl<T, const N: usize> CircularBuffer<T, N> {
     self.len
   }
 
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
   /// overwrites oldest element if full
   pub fn push(&mut self, item: T) {
     self.buf[self.head] = item;
@@ -79,6 +157,17 @@ impl ArgParser {
     None
   }
 
+  /// a flag without a value
+  pub fn take_switch(&mut self, flag: &str) -> bool {
+    match self.args.iter().position(|arg| arg == flag) {
+      Some(pos) => {
+        self.args.remove(pos);
+        true
+      }
+      None => false,
+    }
+  }
+
   pub fn remaining(self) -> Vec<String> {
     self.args
   }
@@ -143,4 +232,13 @@ mod tests {
     assert_eq!(parser.take_flag("-o"), None);
     assert_eq!(parser.remaining(), arr_conv(&[]));
   }
+
+  #[test]
+  fn test_arg_parser_switch() {
+    let mut parser = ArgParser::new(arr_conv(&["--check", "a.o", "-o"]));
+    std::assert!(parser.take_switch("--check"));
+    std::assert!(!parser.take_switch("--check"));
+    std::assert!(parser.take_switch("-o"));
+    assert_eq!(parser.remaining(), arr_conv(&["a.o"]));
+  }
 }
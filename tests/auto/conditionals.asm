@@ -0,0 +1,29 @@
+.equ WIDTH, 128
+.equ DEBUG, 0
+
+.if DEBUG
+  mov %r1, 1
+.else
+  mov %r1, 2
+.endif
+
+.ifdef WIDTH
+  mov %r2, WIDTH
+  .if WIDTH
+    .ifndef HEIGHT
+      mov %r3, 3
+    .endif
+  .else
+    mov %r3, 4
+  .endif
+.endif
+
+.ifdef UNDEFINED
+  .if UNDEFINED ; not evaluated
+    mov %r4, 1
+  .endif
+  invalid instructions are skipped
+.else
+  mov %r4, 5
+.endif
+  hlt ;assert r1=2, r2=128, r3=3, r4=5
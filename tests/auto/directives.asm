@@ -0,0 +1,20 @@
+  lbu %r1, byte
+  lw %r2, aligned
+  mov %r7, filled
+  lbu %r3, %r7, 0
+  lbu %r4, %r7, 2
+  lbu %r5, %r7, 3
+  lw %r6, origin
+  hlt ;assert r1=7, r2=1234, r3=170, r4=170, r5=0, r6=4321, r7=38
+
+byte:
+  .db 7
+  .align 4
+aligned:
+  .dw 1234
+filled:
+  .fill 3, 0xaa
+  .org 0x40
+origin:
+  .dw 4321
+  .align 2
@@ -0,0 +1,30 @@
+; local labels are scoped to the preceding global label
+first:
+  mov %r1, 0
+  .loop:
+    inc %r1
+    cmp %r1, 3
+    jne .loop
+  jmp second.loop ; qualified reference to another scope's local label
+  mov %r3, 1
+
+second:
+  .loop:
+    add %r2, %r2, 2
+    cmp %r2, 4
+    jne .loop
+  hlt ;assert r1=3, r2=4, r3=0
+
+; numeric labels
+numeric:
+  1:
+    inc %r4
+    cmp %r4, 2
+    jeq 1f
+    jmp 1b
+  1:
+  2:
+    inc %r5
+    cmp %r5, 5
+    jne 2b
+  hlt ;assert r4=2, r5=5
@@ -4,3 +4,17 @@ mov %r3, 0b11011
 mov %r4, 0xfafa
 mov %r5, 0
 hlt ;assert r1=3829, r2=507, r3=27, r4=64250, r5=0
+
+mov %r1, -5
+mov %r2, -0x8000
+mov %r3, -0b1
+mov %r4, 'a'
+mov %r5, -'\n'
+mov %r6, 65535
+sub %r7, %r0, -3
+hlt ;assert r1=65531, r2=32768, r3=65535, r4=97, r5=65526, r6=65535, r7=3
+
+mov %r1, ','
+mov %r2, ';' ; a comment; with semicolons
+mov %r3, '\''
+hlt ;assert r1=44, r2=59, r3=39
@@ -0,0 +1,11 @@
+.equ VALUE, 42
+  li %r1, VALUE
+  li %r2, -1
+  la %r3, data
+  lbu %r4, %r3, 0
+  lb %r5, %r3, 1
+  hlt ;assert r1=42, r2=65535, r3=24, r4=255, r5=65408
+
+data:
+  .db 255
+  .db -128
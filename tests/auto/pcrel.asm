@@ -0,0 +1,22 @@
+; pc relative branches
+start:
+  mov %r1, 0
+  .loop:
+    inc %r1
+    cmp %r1, 3
+    bne .loop
+  br .skip
+  mov %r8, 1
+  .skip:
+
+; pc relative data access and address calculation
+  lw %r2, %pc, word
+  add %r3, %pc, word
+  lw %r4, %r3, 0
+  jmp %pc, .done
+  mov %r8, 2
+  .done:
+  hlt ;assert r1=3, r2=4660, r3=48, r4=4660, r8=0
+
+word:
+  .dw 0x1234
@@ -0,0 +1,18 @@
+; label addends and parts of addresses
+start:
+  lbu %r1, %r0, table + 2
+  la %r2, table - 4
+  mov %r3, hi(table)
+  mov %r4, lo(table + 1)
+  lbu %r5, %r0, ptr
+  lbu %r6, %r0, ptr + 1
+  lw %r7, %pc, table + 1
+  hlt ;assert r1=30, r2=28, r3=0, r4=33, r5=32, r6=0, r7=7700
+
+table:
+  .db 10
+  .db 20
+  .db 30
+ptr:
+  .db lo(table)
+  .db hi(table)
@@ -0,0 +1,19 @@
+.data
+value:
+  .dw 1234
+
+.bss
+buf:
+  .skip 4
+end:
+
+.text
+  lw %r1, value
+  sw %r1, buf
+  lw %r2, buf
+  la %r3, buf
+  la %r4, value
+  sub %r5, %r3, %r4
+  la %r6, end
+  sub %r6, %r6, %r3
+  hlt ;assert r1=1234, r2=1234, r5=2, r6=4
@@ -0,0 +1,4 @@
+.bss
+stack:
+  .skip 16
+  .dw stack
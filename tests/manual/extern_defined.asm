@@ -0,0 +1,7 @@
+.extern helper
+.global main
+
+main:
+  jmp helper
+helper:
+  jmp main
@@ -0,0 +1 @@
+.align 3
@@ -0,0 +1,5 @@
+data:
+  .db 256
+  .db -129
+  li %r1, data
+  la %r1, 5
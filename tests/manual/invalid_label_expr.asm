@@ -0,0 +1,7 @@
+start:
+  lbu %r1, %r0, table + x
+  mov %r2, mid(table)
+  br hi(start)
+
+table:
+  .db 1
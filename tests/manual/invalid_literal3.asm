@@ -0,0 +1 @@
+add %r3, %r2, -32769
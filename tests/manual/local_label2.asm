@@ -0,0 +1,3 @@
+start:
+  jmp 1f
+  jmp 1b
@@ -0,0 +1,2 @@
+start:
+  jmp 1f
@@ -0,0 +1,8 @@
+start:
+  add %r9, %r2, %r1
+  mov %r1, 0x4g
+  .db 0x1234
+  invalid %r1
+start:
+  jmp .missing
+.unused:
@@ -0,0 +1,3 @@
+start:
+  .skip 8
+  .org 4
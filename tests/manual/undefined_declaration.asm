@@ -0,0 +1,5 @@
+.global main
+.local helper
+
+start:
+  jmp start
@@ -0,0 +1,5 @@
+.if 1
+  nop
+.else
+  nop
+.else
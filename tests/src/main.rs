@@ -6,6 +6,7 @@ use regex::Regex;
 use owo_colors::OwoColorize;
 use q16::Register;
 use q16::asm::Assembler;
+use q16::link::LinkConfig;
 use q16::emu::Emulator;
 use q16::util::err;
 
@@ -57,9 +58,9 @@ fn run_test(path: &Path) -> Result<(), String> {
   let src = fs::read_to_string(path).unwrap();
   let obj = match assembler.assemble(&src) {
     Ok(_) => assembler.obj,
-    Err(e) => return Err(e.1),
+    Err(e) => return Err(e[0].msg.clone()),
   };
-  let bin = obj.out_bin()?;
+  let bin = obj.out_bin(&LinkConfig::default())?;
 
   let mut emu = Emulator::new();
   emu.memory.splice(0..bin.len(), bin);